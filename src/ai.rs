@@ -0,0 +1,127 @@
+use crate::vehicle::{CarPreset, Controls, Vehicle};
+use rand::prelude::*;
+use rusty_engine::prelude::*;
+use std::f32::consts::PI;
+
+/// A waypoint counts as reached once the car is this close to it.
+const WAYPOINT_RADIUS: f32 = 80.0;
+const STEERING_GAIN: f32 = 3.0;
+
+/// How well a computer driver races.
+#[derive(Clone, Copy, Debug)]
+pub struct Skill {
+    /// Share of the car's top speed the driver is willing to use.
+    pub top_speed: f32,
+    /// Distance before a corner at which the driver starts slowing down for it.
+    pub braking_point: f32,
+    /// Average number of mistakes per second, like running wide or braking late.
+    pub mistake_rate: f32,
+}
+
+/// Computer opponents, in grid order.
+pub const RIVALS: [(CarPreset, Skill); 4] = [
+    (
+        CarPreset::Red,
+        Skill {
+            top_speed: 0.95,
+            braking_point: 140.0,
+            mistake_rate: 0.05,
+        },
+    ),
+    (
+        CarPreset::Blue,
+        Skill {
+            top_speed: 0.9,
+            braking_point: 170.0,
+            mistake_rate: 0.1,
+        },
+    ),
+    (
+        CarPreset::Yellow,
+        Skill {
+            top_speed: 0.85,
+            braking_point: 200.0,
+            mistake_rate: 0.15,
+        },
+    ),
+    (
+        CarPreset::Black,
+        Skill {
+            top_speed: 0.8,
+            braking_point: 220.0,
+            mistake_rate: 0.2,
+        },
+    ),
+];
+
+/// Follows a racing line made of waypoints.
+#[derive(Clone, Copy, Debug)]
+pub struct Driver {
+    pub skill: Skill,
+    pub next_waypoint: usize,
+    /// Steering error applied while a mistake lasts.
+    mistake_steering: f32,
+    mistake_time: f32,
+}
+
+impl Driver {
+    pub fn new(skill: Skill) -> Self {
+        Self {
+            skill,
+            next_waypoint: 0,
+            mistake_steering: 0.0,
+            mistake_time: 0.0,
+        }
+    }
+
+    pub fn controls(
+        &mut self,
+        car: &Vehicle,
+        racing_line: &[Vec2],
+        delta: f32,
+        rng: &mut impl Rng,
+    ) -> Controls {
+        if racing_line.is_empty() {
+            return Controls::default();
+        }
+
+        let waypoint = |index: usize| racing_line[index % racing_line.len()];
+        if car.position.distance(waypoint(self.next_waypoint)) < WAYPOINT_RADIUS {
+            self.next_waypoint = (self.next_waypoint + 1) % racing_line.len();
+        }
+
+        let previous = waypoint(self.next_waypoint + racing_line.len() - 1);
+        let target = waypoint(self.next_waypoint);
+        let after = waypoint(self.next_waypoint + 1);
+
+        // Slow down ahead of sharp corners, down to half speed for a hairpin.
+        let top_speed = car.handling.top_speed * self.skill.top_speed;
+        let corner = (target - previous).angle_between(after - target).abs();
+        let target_speed = if car.position.distance(target) < self.skill.braking_point {
+            top_speed * (1.0 - 0.5 * corner / PI)
+        } else {
+            top_speed
+        };
+
+        let speed = car.speed();
+        let mut steering = car.forward().angle_between(target - car.position) * STEERING_GAIN;
+
+        self.mistake_time -= delta;
+        if self.mistake_time > 0.0 {
+            steering += self.mistake_steering;
+        } else if rng.gen::<f32>() < self.skill.mistake_rate * delta {
+            self.mistake_time = rng.gen_range(0.3..0.8);
+            self.mistake_steering = if rng.gen() { 0.8 } else { -0.8 };
+        }
+
+        Controls {
+            throttle: if speed < target_speed { 1.0 } else { 0.0 },
+            brake: if speed > target_speed + 30.0 {
+                1.0
+            } else {
+                0.0
+            },
+            steering: steering.clamp(-1.0, 1.0),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use rusty_engine::prelude::*;
+
+/// Share of the distance to where the camera wants to be that is left after one second.
+const FOLLOW_LAG: f32 = 0.02;
+/// The camera leads the cars by where they will be this many seconds from now.
+const LOOK_AHEAD_TIME: f32 = 0.4;
+const MAX_LOOK_AHEAD: f32 = 250.0;
+/// Zoom at a standstill, and at `ZOOM_OUT_SPEED` pixels per second and beyond.
+const MAX_ZOOM: f32 = 1.0;
+const MIN_ZOOM: f32 = 0.7;
+const ZOOM_OUT_SPEED: f32 = 500.0;
+/// Cars far apart are kept in view down to this zoom, with this much room around them.
+const MIN_FIT_ZOOM: f32 = 0.3;
+const FIT_MARGIN: f32 = 300.0;
+/// Wall impacts at this speed and above shake the screen as hard as it goes.
+const FULL_SHAKE_IMPACT: f32 = 300.0;
+/// Furthest the view is thrown off by a shake, in pixels on the screen.
+const MAX_SHAKE: f32 = 20.0;
+/// Shake lost per second.
+const SHAKE_DECAY: f32 = 2.0;
+/// Radians per second the shake wobbles at.
+const SHAKE_FREQUENCY: f32 = 45.0;
+
+/// What the window shows of the world. The engine always draws around the window center, so
+/// world sprites go through `screen_position` every frame while the HUD is placed on the screen
+/// as is.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    /// World point at the center of the window.
+    pub position: Vec2,
+    /// Screen pixels per world pixel.
+    pub zoom: f32,
+    /// Between 0 and 1. The view shakes with the square of it, so small knocks stay subtle.
+    trauma: f32,
+    /// Seconds the camera has run, which drives the wobble of a shake.
+    time: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(Vec2::ZERO)
+    }
+}
+
+impl Camera {
+    /// A camera at rest, looking at `position`.
+    pub fn new(position: Vec2) -> Self {
+        Self {
+            position,
+            zoom: MAX_ZOOM,
+            trauma: 0.0,
+            time: 0.0,
+        }
+    }
+
+    /// Glides towards `cars`, given by position and velocity, ahead of where they are heading.
+    /// Faster cars get a wider view, and so do cars that are far apart in a `window` this size.
+    pub fn update(&mut self, delta: f32, cars: &[(Vec2, Vec2)], window: Vec2) {
+        self.time += delta;
+        self.trauma = (self.trauma - SHAKE_DECAY * delta).max(0.0);
+        if cars.is_empty() {
+            return;
+        }
+
+        let count = cars.len() as f32;
+        let (center, velocity) = cars
+            .iter()
+            .fold((Vec2::ZERO, Vec2::ZERO), |(center, velocity), &(p, v)| {
+                (center + p / count, velocity + v / count)
+            });
+        let target = center + (velocity * LOOK_AHEAD_TIME).clamp_length_max(MAX_LOOK_AHEAD);
+
+        let speed = (velocity.length() / ZOOM_OUT_SPEED).min(1.0);
+        let mut zoom = MAX_ZOOM + (MIN_ZOOM - MAX_ZOOM) * speed;
+        let (min, max) = cars.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), &(position, _)| (min.min(position), max.max(position)),
+        );
+        let room = window / (max - min + Vec2::splat(FIT_MARGIN));
+        zoom = zoom.min(room.x).min(room.y).max(MIN_FIT_ZOOM);
+
+        let lag = FOLLOW_LAG.powf(delta);
+        self.position = target + (self.position - target) * lag;
+        self.zoom = zoom + (self.zoom - zoom) * lag;
+    }
+
+    /// Adds shake for a wall impact at `impact` pixels per second.
+    pub fn shake(&mut self, impact: f32) {
+        self.trauma = (self.trauma + impact / FULL_SHAKE_IMPACT).min(1.0);
+    }
+
+    /// How far the shake throws the view off right now, in screen pixels.
+    fn shake_offset(&self) -> Vec2 {
+        let wobble = Vec2::new(
+            (self.time * SHAKE_FREQUENCY).sin(),
+            (self.time * SHAKE_FREQUENCY * 1.3 + 1.0).sin(),
+        );
+        wobble * MAX_SHAKE * self.trauma * self.trauma
+    }
+
+    /// Where a world point is drawn, relative to the window center.
+    pub fn screen_position(&self, world: Vec2) -> Vec2 {
+        (world - self.position) * self.zoom + self.shake_offset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_leads_the_car_and_zooms_out_with_speed() {
+        let window = Vec2::new(1920.0, 1080.0);
+        let mut camera = Camera::default();
+        let parked = [(Vec2::new(100.0, 0.0), Vec2::ZERO)];
+        for _ in 0..120 {
+            camera.update(1.0 / 60.0, &parked, window);
+        }
+        assert!(camera.position.distance(Vec2::new(100.0, 0.0)) < 1.0);
+        assert!((camera.zoom - MAX_ZOOM).abs() < 0.001);
+        assert!(camera.screen_position(Vec2::new(100.0, 0.0)).length() < 1.0);
+
+        let fast = [(Vec2::new(100.0, 0.0), Vec2::new(0.0, ZOOM_OUT_SPEED))];
+        for _ in 0..120 {
+            camera.update(1.0 / 60.0, &fast, window);
+        }
+        assert!(camera.position.y > 150.0);
+        assert!((camera.zoom - MIN_ZOOM).abs() < 0.01);
+
+        let apart = [
+            (Vec2::new(-2000.0, 0.0), Vec2::ZERO),
+            (Vec2::new(2000.0, 0.0), Vec2::ZERO),
+        ];
+        for _ in 0..120 {
+            camera.update(1.0 / 60.0, &apart, window);
+        }
+        assert!(camera.screen_position(Vec2::new(2000.0, 0.0)).x < window.x / 2.0);
+    }
+
+    #[test]
+    fn shake_wears_off() {
+        let window = Vec2::new(1920.0, 1080.0);
+        let mut camera = Camera::new(Vec2::ZERO);
+        camera.shake(FULL_SHAKE_IMPACT * 2.0);
+        camera.update(0.01, &[], window);
+        assert!(camera.screen_position(Vec2::ZERO) != Vec2::ZERO);
+
+        camera.update(1.0 / SHAKE_DECAY, &[], window);
+        assert_eq!(camera.screen_position(Vec2::ZERO), Vec2::ZERO);
+    }
+}
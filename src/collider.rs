@@ -0,0 +1,181 @@
+use rusty_engine::prelude::*;
+
+/// Parses the contents of a `.collider` file, as written by rusty_engine's collider editor:
+/// `Poly([(x, y), ...])`, or `NoCollider` which gives no points.
+pub fn parse(source: &str) -> Result<Vec<Vec2>, String> {
+    let mut parser = Parser {
+        source,
+        position: 0,
+    };
+
+    if parser.eat_word("NoCollider") {
+        parser.expect_end()?;
+        return Ok(Vec::new());
+    }
+
+    parser.expect_word("Poly")?;
+    parser.expect('(')?;
+    parser.expect('[')?;
+
+    let mut points = Vec::new();
+    while !parser.eat(']') {
+        parser.expect('(')?;
+        let x = parser.number()?;
+        parser.expect(',')?;
+        let y = parser.number()?;
+        parser.eat(',');
+        parser.expect(')')?;
+        points.push(Vec2::new(x, y));
+
+        if !parser.eat(',') {
+            parser.expect(']')?;
+            break;
+        }
+    }
+
+    parser.eat(',');
+    parser.expect(')')?;
+    parser.expect_end()?;
+
+    Ok(points)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    /// Skips whitespace and `//` comments.
+    fn skip_blank(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+
+            if !trimmed.starts_with("//") {
+                return;
+            }
+            self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_blank();
+        if self.rest().starts_with(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}'", expected)))
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.skip_blank();
+        if self.rest().starts_with(word) {
+            self.position += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), String> {
+        if self.eat_word(word) {
+            Ok(())
+        } else {
+            Err(self.error(word))
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), String> {
+        self.skip_blank();
+        if self.rest().is_empty() {
+            Ok(())
+        } else {
+            Err(self.error("end of file"))
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_blank();
+        let length = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(self.rest().len());
+
+        match self.rest()[..length].parse() {
+            Ok(number) if length > 0 => {
+                self.position += length;
+                Ok(number)
+            }
+            _ => Err(self.error("a number")),
+        }
+    }
+
+    fn error(&self, expected: &str) -> String {
+        let line = self.source[..self.position].matches('\n').count() + 1;
+        format!("expected {} on line {}", expected, line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parses_integer_and_float_points() {
+        let points = parse("Poly([\n    (-45, 33.5),\n    (38.5, -1e1),\n])").unwrap();
+
+        assert_eq!(points, vec![Vec2::new(-45.0, 33.5), Vec2::new(38.5, -10.0)]);
+    }
+
+    #[test]
+    fn parses_empty_collider() {
+        assert_eq!(parse("NoCollider\n"), Ok(Vec::new()));
+        assert_eq!(parse("Poly([])"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        assert_eq!(
+            parse("Poly([\n    (1.0, 2.0),\n    (oops, 2.0),\n])"),
+            Err("expected a number on line 3".to_string())
+        );
+        assert!(parse("Poly([(1.0, 2.0)]) trailing").is_err());
+    }
+
+    #[test]
+    fn parses_every_shipped_collider() {
+        for directory in [
+            "assets/track",
+            "assets/sprite/racing",
+            "assets/sprite/rolling",
+        ] {
+            for entry in fs::read_dir(directory).unwrap() {
+                let path = entry.unwrap().path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "collider")
+                {
+                    let points = parse(&fs::read_to_string(&path).unwrap())
+                        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+                    assert!(points.len() >= 3, "{}", path.display());
+                }
+            }
+        }
+    }
+}
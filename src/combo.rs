@@ -0,0 +1,80 @@
+/// Seconds the multiplier holds after a collection before it starts dropping, one step per
+/// window that passes without another.
+const COMBO_WINDOW: f32 = 2.5;
+pub const MAX_MULTIPLIER: i32 = 5;
+
+/// Collections in quick succession, as a score multiplier.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Combo {
+    multiplier: i32,
+    timer: f32,
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self {
+            multiplier: 1,
+            timer: 0.0,
+        }
+    }
+}
+
+impl Combo {
+    pub fn multiplier(&self) -> i32 {
+        self.multiplier
+    }
+
+    /// Counts a collection and returns the multiplier it scores with. The first one of a combo
+    /// scores plainly, every quick one after that with one more.
+    pub fn collect(&mut self) -> i32 {
+        if self.timer > 0.0 {
+            self.multiplier = (self.multiplier + 1).min(MAX_MULTIPLIER);
+        }
+        self.timer = COMBO_WINDOW;
+        self.multiplier
+    }
+
+    pub fn tick(&mut self, delta: f32) {
+        if self.timer <= 0.0 {
+            return;
+        }
+
+        self.timer -= delta;
+        if self.timer <= 0.0 && self.multiplier > 1 {
+            self.multiplier -= 1;
+            self.timer += COMBO_WINDOW;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combo_builds_up_and_decays_one_step_at_a_time() {
+        let mut combo = Combo::default();
+        assert_eq!(combo.collect(), 1);
+        combo.tick(1.0);
+        assert_eq!(combo.collect(), 2);
+        assert_eq!(combo.collect(), 3);
+
+        combo.tick(COMBO_WINDOW + 0.1);
+        assert_eq!(combo.multiplier(), 2);
+        combo.tick(COMBO_WINDOW);
+        assert_eq!(combo.multiplier(), 1);
+        combo.tick(COMBO_WINDOW);
+        assert_eq!(combo.collect(), 1);
+
+        for _ in 0..10 {
+            combo.collect();
+        }
+        assert_eq!(combo.multiplier(), MAX_MULTIPLIER);
+        combo.reset();
+        assert_eq!(combo.multiplier(), 1);
+    }
+}
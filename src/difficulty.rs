@@ -0,0 +1,146 @@
+use crate::ai::Skill;
+use serde::{Deserialize, Serialize};
+
+/// Seconds of an endless run it takes to get one ramp step harder.
+const ENDLESS_RAMP_TIME: f32 = 60.0;
+/// Endless stops getting harder after this many ramp steps.
+const ENDLESS_MAX_RAMP: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    /// Starts out like `Normal` and keeps getting harder the longer the run lasts. There is no
+    /// finish, the run goes on until the car is wrecked.
+    Endless,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Endless,
+    ];
+
+    /// How the game plays `time` seconds into a run.
+    pub fn tuning(self, time: f32) -> Tuning {
+        match self {
+            Difficulty::Easy => Tuning {
+                damage: 0.5,
+                spawn_interval: (2.5, 5.0),
+                max_enemies: 4,
+                enemy_speed: 0.75,
+                enemy_reach: 0.75,
+                behaviour_weights: [5.0, 2.0, 0.0, 1.0, 2.0],
+                rival_pace: 0.9,
+                rival_mistakes: 2.0,
+            },
+            Difficulty::Normal => Tuning::default(),
+            Difficulty::Hard => Tuning {
+                damage: 1.5,
+                spawn_interval: (1.0, 2.5),
+                max_enemies: 8,
+                enemy_speed: 1.3,
+                enemy_reach: 1.25,
+                behaviour_weights: [3.0, 2.0, 2.0, 2.0, 1.0],
+                rival_pace: 1.05,
+                rival_mistakes: 0.5,
+            },
+            Difficulty::Endless => {
+                let ramp = (time / ENDLESS_RAMP_TIME).min(ENDLESS_MAX_RAMP);
+                let [oscillating, patrolling, chasing, hazard, pushable] =
+                    Tuning::default().behaviour_weights;
+                Tuning {
+                    damage: 1.0 + 0.25 * ramp,
+                    spawn_interval: (1.5 / (1.0 + 0.25 * ramp), 3.5 / (1.0 + 0.25 * ramp)),
+                    max_enemies: 6 + ramp as usize,
+                    enemy_speed: 1.0 + 0.15 * ramp,
+                    enemy_reach: 1.0 + 0.1 * ramp,
+                    behaviour_weights: [
+                        oscillating,
+                        patrolling,
+                        chasing + ramp,
+                        hazard + ramp,
+                        pushable,
+                    ],
+                    rival_pace: 1.0 + 0.02 * ramp,
+                    rival_mistakes: 1.0 / (1.0 + ramp),
+                }
+            }
+        }
+    }
+}
+
+/// Everything a difficulty changes. The default is `Normal`, which plays like the game always
+/// did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+    /// Multiplies every damage rate.
+    pub damage: f32,
+    /// Seconds between two enemy spawns are picked from this range.
+    pub spawn_interval: (f32, f32),
+    /// Spawning stops while this many spawned enemies are on the track. Enemies placed by the
+    /// track and cones do not count.
+    pub max_enemies: usize,
+    /// Multiplies how fast spawned enemies move.
+    pub enemy_speed: f32,
+    /// Multiplies how far spawned enemies swing back and forth.
+    pub enemy_reach: f32,
+    /// How often each behaviour is spawned, relative to the others: oscillating, patrolling,
+    /// chasing, hazard and pushable.
+    pub behaviour_weights: [f32; 5],
+    /// Multiplies the share of their top speed rivals use.
+    pub rival_pace: f32,
+    /// Multiplies how often rivals make mistakes.
+    pub rival_mistakes: f32,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            damage: 1.0,
+            spawn_interval: (1.5, 3.5),
+            max_enemies: 6,
+            enemy_speed: 1.0,
+            enemy_reach: 1.0,
+            behaviour_weights: [4.0, 2.0, 1.0, 2.0, 1.0],
+            rival_pace: 1.0,
+            rival_mistakes: 1.0,
+        }
+    }
+}
+
+impl Tuning {
+    /// A rival's own skill, adjusted to the difficulty. Nobody drives faster than their car can.
+    pub fn skill(&self, skill: Skill) -> Skill {
+        Skill {
+            top_speed: (skill.top_speed * self.rival_pace).min(1.0),
+            mistake_rate: skill.mistake_rate * self.rival_mistakes,
+            ..skill
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endless_ramps_up_from_normal_and_levels_off() {
+        let start = Difficulty::Endless.tuning(0.0);
+        assert_eq!(start, Difficulty::Normal.tuning(0.0));
+        assert_eq!(Difficulty::Normal.tuning(600.0), start);
+
+        let later = Difficulty::Endless.tuning(ENDLESS_RAMP_TIME * 2.0);
+        assert!(later.damage > start.damage);
+        assert!(later.spawn_interval.1 < start.spawn_interval.1);
+        assert!(later.max_enemies > start.max_enemies);
+        assert!(later.behaviour_weights[2] > start.behaviour_weights[2]);
+
+        let end = Difficulty::Endless.tuning(ENDLESS_RAMP_TIME * ENDLESS_MAX_RAMP);
+        assert_eq!(Difficulty::Endless.tuning(10_000.0), end);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::geometry::Contact;
+use rusty_engine::prelude::*;
+use serde::Deserialize;
+
+/// Share of a pushed enemy's speed that is left after one second of sliding.
+const PUSHED_SLIDE: f32 = 0.05;
+/// Pushed enemies fly off a little faster than the car that hit them.
+const PUSH_BOOST: f32 = 1.2;
+
+/// Every enemy collider, relative to the `assets` directory, so they can be loaded up front.
+pub const COLLIDERS: [&str; 5] = [
+    "sprite/racing/barrel_red.collider",
+    "sprite/racing/barrel_blue.collider",
+    "sprite/rolling/ball_red.collider",
+    "sprite/rolling/hole_start.collider",
+    "sprite/racing/cone_straight.collider",
+];
+
+/// How an enemy moves and what touching it is worth.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum Behaviour {
+    /// Swings back and forth through its position along `direction`. `frequency` is in radians
+    /// per second and `phase` in radians, so enemies placed side by side need not move in step.
+    Oscillating {
+        direction: f32,
+        amplitude: f32,
+        #[serde(default = "one")]
+        frequency: f32,
+        #[serde(default)]
+        phase: f32,
+    },
+    /// Drives through the points of `path` in order at `speed`, then starts over.
+    Patrolling { path: Vec<Vec2>, speed: f32 },
+    /// Heads for the closest player still in the race.
+    Chasing { speed: f32 },
+    /// Never moves, and costs points instead of giving them.
+    Hazard,
+    /// Gets pushed around by cars and cannot be collected.
+    Pushable,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+impl Behaviour {
+    pub fn sprite(&self) -> SpritePreset {
+        match self {
+            Behaviour::Oscillating { .. } => SpritePreset::RacingBarrelRed,
+            Behaviour::Patrolling { .. } => SpritePreset::RacingBarrelBlue,
+            Behaviour::Chasing { .. } => SpritePreset::RollingBallRed,
+            Behaviour::Hazard => SpritePreset::RollingHoleStart,
+            Behaviour::Pushable => SpritePreset::RacingConeStraight,
+        }
+    }
+
+    /// Collider of the sprite, one of `COLLIDERS`.
+    pub fn collider(&self) -> &'static str {
+        match self {
+            Behaviour::Oscillating { .. } => COLLIDERS[0],
+            Behaviour::Patrolling { .. } => COLLIDERS[1],
+            Behaviour::Chasing { .. } => COLLIDERS[2],
+            Behaviour::Hazard => COLLIDERS[3],
+            Behaviour::Pushable => COLLIDERS[4],
+        }
+    }
+
+    /// Score for driving into the enemy. Enemies worth nothing stay on the track.
+    pub fn points(&self) -> i32 {
+        match self {
+            Behaviour::Oscillating { .. } => 10,
+            Behaviour::Patrolling { .. } => 15,
+            Behaviour::Chasing { .. } => -15,
+            Behaviour::Hazard => -10,
+            Behaviour::Pushable => 0,
+        }
+    }
+
+    pub fn is_collectable(&self) -> bool {
+        self.points() != 0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Enemy {
+    pub label: String,
+    pub behaviour: Behaviour,
+    /// Where the enemy was placed, and the center of an oscillation.
+    pub position: Vec2,
+    /// Where the enemy is right now.
+    pub translation: Vec2,
+    pub velocity: Vec2,
+    /// Whether the enemy came from a spawn zone rather than the track. Only spawned enemies count
+    /// towards the enemy cap.
+    pub spawned: bool,
+    /// Point of the patrol path the enemy is heading for.
+    waypoint: usize,
+}
+
+impl Enemy {
+    pub fn new(label: String, behaviour: Behaviour, position: Vec2) -> Self {
+        Self {
+            label,
+            behaviour,
+            position,
+            translation: position,
+            velocity: Vec2::ZERO,
+            spawned: false,
+            waypoint: 0,
+        }
+    }
+
+    /// Moves the enemy on by `delta` seconds, `time` seconds into the run. Chasers go after
+    /// `target`, and stop when there is nobody to chase.
+    pub fn update(&mut self, time: f32, delta: f32, target: Option<Vec2>) {
+        match &self.behaviour {
+            Behaviour::Oscillating {
+                direction,
+                amplitude,
+                frequency,
+                phase,
+            } => {
+                let direction = Vec2::new(direction.cos(), direction.sin());
+                self.translation =
+                    self.position + direction * *amplitude * (time * frequency + phase).sin();
+            }
+            Behaviour::Patrolling { path, speed } => {
+                if let Some(&waypoint) = path.get(self.waypoint) {
+                    self.translation = move_towards(self.translation, waypoint, speed * delta);
+                    if self.translation == waypoint {
+                        self.waypoint = (self.waypoint + 1) % path.len();
+                    }
+                }
+            }
+            Behaviour::Chasing { speed } => {
+                if let Some(target) = target {
+                    self.translation = move_towards(self.translation, target, speed * delta);
+                }
+            }
+            Behaviour::Hazard => {}
+            Behaviour::Pushable => {
+                self.translation += self.velocity * delta;
+                self.velocity *= PUSHED_SLIDE.powf(delta);
+            }
+        }
+    }
+
+    /// Shoves the enemy out of a car moving at `car_velocity`. `contact` is the enemy's contact
+    /// with the car.
+    pub fn push(&mut self, contact: Contact, car_velocity: Vec2) {
+        self.translation += contact.normal * contact.depth;
+
+        let closing = car_velocity.dot(contact.normal) - self.velocity.dot(contact.normal);
+        if closing > 0.0 {
+            self.velocity += contact.normal * closing * PUSH_BOOST;
+        }
+    }
+}
+
+fn move_towards(from: Vec2, to: Vec2, distance: f32) -> Vec2 {
+    let offset = to - from;
+    if offset.length() <= distance {
+        to
+    } else {
+        from + offset.normalize() * distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enemy(behaviour: Behaviour) -> Enemy {
+        Enemy::new("enemy_1".to_string(), behaviour, Vec2::ZERO)
+    }
+
+    #[test]
+    fn behaviours_move_their_own_way() {
+        let oscillating = |phase| {
+            let mut enemy = enemy(Behaviour::Oscillating {
+                direction: 0.0,
+                amplitude: 50.0,
+                frequency: 2.0,
+                phase,
+            });
+            enemy.update(1.0, 0.1, None);
+            enemy.translation
+        };
+        assert_ne!(oscillating(0.0), oscillating(1.0));
+
+        let mut patrol = enemy(Behaviour::Patrolling {
+            path: vec![Vec2::new(10.0, 0.0), Vec2::ZERO],
+            speed: 100.0,
+        });
+        for _ in 0..15 {
+            patrol.update(0.0, 0.01, None);
+        }
+        assert_eq!(patrol.translation, Vec2::new(5.0, 0.0));
+
+        let mut chaser = enemy(Behaviour::Chasing { speed: 100.0 });
+        chaser.update(0.0, 0.5, Some(Vec2::new(0.0, 200.0)));
+        assert_eq!(chaser.translation, Vec2::new(0.0, 50.0));
+        chaser.update(0.0, 0.5, None);
+        assert_eq!(chaser.translation, Vec2::new(0.0, 50.0));
+
+        let mut hazard = enemy(Behaviour::Hazard);
+        hazard.update(1.0, 1.0, Some(Vec2::ONE));
+        assert_eq!(hazard.translation, Vec2::ZERO);
+    }
+
+    #[test]
+    fn pushed_enemy_slides_and_slows_down() {
+        let mut cone = enemy(Behaviour::Pushable);
+        let contact = Contact {
+            normal: Vec2::X,
+            depth: 2.0,
+        };
+        cone.push(contact, Vec2::new(100.0, 50.0));
+        assert_eq!(cone.translation, Vec2::new(2.0, 0.0));
+        assert!((cone.velocity - Vec2::new(120.0, 0.0)).length() < 0.001);
+
+        cone.update(0.0, 0.1, None);
+        assert!(cone.translation.x > 2.0 && cone.velocity.x < 120.0);
+        assert!(!cone.behaviour.is_collectable());
+    }
+}
@@ -0,0 +1,44 @@
+use crate::geometry::Side;
+use crate::pickup::Kind;
+use rusty_engine::prelude::*;
+
+/// Gameplay events produced by each simulation step, kept per player. Any number of systems can
+/// read them, for example to play sounds or to remove sprites of collected enemies.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// The car bounced off the wall. `impact` is the speed it had into the wall.
+    WallHit {
+        impact: f32,
+        side: Side,
+    },
+    /// The car ran into another car. `impact` is the speed the two cars closed in with.
+    CarHit {
+        impact: f32,
+        side: Side,
+    },
+    LeftTrack,
+    ReturnedToTrack,
+    /// The car touched an enemy at `position`, and the enemy is gone now. `points` is what the
+    /// enemy is worth before any multiplier, negative for enemies that are better avoided.
+    EnemyCollected {
+        label: String,
+        points: i32,
+        position: Vec2,
+    },
+    PickupCollected {
+        label: String,
+        kind: Kind,
+    },
+    CheckpointCrossed(usize),
+    FinishLineCrossed,
+    LapCompleted {
+        time: f32,
+    },
+    /// Points went onto the score at `position`. Rewards are multiplied by `multiplier`, and
+    /// `points` is the total.
+    Scored {
+        points: i32,
+        multiplier: i32,
+        position: Vec2,
+    },
+}
@@ -0,0 +1,165 @@
+use gilrs::{Axis, Button, Gamepad, Gilrs};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often the gamepads are read, well within a frame.
+const POLL_INTERVAL: Duration = Duration::from_millis(4);
+/// Stick travel around the center that counts as nothing, so a worn stick does not steer.
+const DEAD_ZONE: f32 = 0.15;
+
+/// Part of a gamepad an action can be bound to. Sticks are split into their four directions, so
+/// one stick can steer both ways with a binding for each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum PadInput {
+    LeftStickLeft,
+    LeftStickRight,
+    LeftStickUp,
+    LeftStickDown,
+    LeftTrigger,
+    RightTrigger,
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    DPadLeft,
+    DPadRight,
+    DPadUp,
+    DPadDown,
+    Start,
+    Select,
+}
+
+impl PadInput {
+    /// In declaration order, which is also the order of `Pad`'s values.
+    pub const ALL: [PadInput; 18] = [
+        PadInput::LeftStickLeft,
+        PadInput::LeftStickRight,
+        PadInput::LeftStickUp,
+        PadInput::LeftStickDown,
+        PadInput::LeftTrigger,
+        PadInput::RightTrigger,
+        PadInput::South,
+        PadInput::East,
+        PadInput::West,
+        PadInput::North,
+        PadInput::LeftBumper,
+        PadInput::RightBumper,
+        PadInput::DPadLeft,
+        PadInput::DPadRight,
+        PadInput::DPadUp,
+        PadInput::DPadDown,
+        PadInput::Start,
+        PadInput::Select,
+    ];
+
+    /// How far the input is pushed on `gamepad`, between 0 and 1.
+    fn read(self, gamepad: &Gamepad) -> f32 {
+        let button = |button| {
+            if gamepad.is_pressed(button) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+
+        match self {
+            PadInput::LeftStickLeft => stick(-gamepad.value(Axis::LeftStickX)),
+            PadInput::LeftStickRight => stick(gamepad.value(Axis::LeftStickX)),
+            PadInput::LeftStickUp => stick(gamepad.value(Axis::LeftStickY)),
+            PadInput::LeftStickDown => stick(-gamepad.value(Axis::LeftStickY)),
+            PadInput::LeftTrigger => trigger(Button::LeftTrigger2),
+            PadInput::RightTrigger => trigger(Button::RightTrigger2),
+            PadInput::South => button(Button::South),
+            PadInput::East => button(Button::East),
+            PadInput::West => button(Button::West),
+            PadInput::North => button(Button::North),
+            PadInput::LeftBumper => button(Button::LeftTrigger),
+            PadInput::RightBumper => button(Button::RightTrigger),
+            PadInput::DPadLeft => button(Button::DPadLeft),
+            PadInput::DPadRight => button(Button::DPadRight),
+            PadInput::DPadUp => button(Button::DPadUp),
+            PadInput::DPadDown => button(Button::DPadDown),
+            PadInput::Start => button(Button::Start),
+            PadInput::Select => button(Button::Select),
+        }
+    }
+}
+
+/// One direction of a stick axis, with the dead zone taken out and the rest stretched back to the
+/// full range.
+fn stick(value: f32) -> f32 {
+    ((value - DEAD_ZONE) / (1.0 - DEAD_ZONE)).clamp(0.0, 1.0)
+}
+
+/// How far every input of one gamepad is pushed, between 0 and 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pad([f32; PadInput::ALL.len()]);
+
+impl Pad {
+    pub fn value(&self, input: PadInput) -> f32 {
+        self.0[input as usize]
+    }
+
+    pub fn set(&mut self, input: PadInput, value: f32) {
+        self.0[input as usize] = value;
+    }
+
+    fn read(gamepad: &Gamepad) -> Self {
+        let mut pad = Pad::default();
+        for input in PadInput::ALL {
+            pad.set(input, input.read(gamepad));
+        }
+        pad
+    }
+}
+
+/// The connected gamepads, in the order they were connected. The engine does not hand gamepads
+/// to logic functions, and gilrs cannot move between threads, so they are read on a thread of
+/// their own that shares what it sees.
+#[derive(Clone, Debug, Default)]
+pub struct Gamepads {
+    pads: Arc<Mutex<Vec<Pad>>>,
+}
+
+impl Gamepads {
+    /// Starts reading. The thread stops once every copy of the returned value is dropped.
+    pub fn start() -> Self {
+        let gamepads = Self::default();
+        let shared = Arc::downgrade(&gamepads.pads);
+
+        thread::spawn(move || {
+            let mut gilrs = match Gilrs::new() {
+                Ok(gilrs) => gilrs,
+                Err(e) => {
+                    eprintln!("Could not read gamepads: {}", e);
+                    return;
+                }
+            };
+
+            while let Some(pads) = shared.upgrade() {
+                // Events are what keep the gamepad state up to date.
+                while gilrs.next_event().is_some() {}
+                let connected = gilrs.gamepads().map(|(_, gamepad)| Pad::read(&gamepad));
+                if let Ok(mut pads) = pads.lock() {
+                    *pads = connected.collect();
+                }
+                drop(pads);
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        gamepads
+    }
+
+    pub fn pads(&self) -> Vec<Pad> {
+        self.pads
+            .lock()
+            .map(|pads| pads.clone())
+            .unwrap_or_default()
+    }
+}
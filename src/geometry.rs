@@ -0,0 +1,276 @@
+use crate::collider;
+use rusty_engine::prelude::*;
+use std::fs;
+use std::path::Path;
+
+const ASSET_DIRECTORY: &str = "assets";
+
+/// Polygon in the same shape rusty_engine uses for sprite colliders. The overlap and contact
+/// tests assume it is convex, like the engine does.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polygon {
+    pub points: Vec<Vec2>,
+}
+
+/// How two overlapping polygons touch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// Unit vector pointing away from the other polygon. Moving this polygon by
+    /// `normal * depth` separates the two.
+    pub normal: Vec2,
+    pub depth: f32,
+}
+
+/// Part of a body that touched something, relative to the direction the body is facing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Front,
+    Rear,
+    Left,
+    Right,
+}
+
+impl Contact {
+    /// Which side of a body facing `heading` was hit.
+    pub fn side(&self, heading: f32) -> Side {
+        let forward = Vec2::new(heading.cos(), heading.sin());
+        // The normal points away from the obstacle, so the obstacle is on the opposite side.
+        let towards_obstacle = -self.normal;
+        let along = towards_obstacle.dot(forward);
+        let across = towards_obstacle.dot(forward.perp());
+
+        if along.abs() >= across.abs() {
+            if along > 0.0 {
+                Side::Front
+            } else {
+                Side::Rear
+            }
+        } else if across > 0.0 {
+            Side::Left
+        } else {
+            Side::Right
+        }
+    }
+}
+
+impl Polygon {
+    pub fn new(points: Vec<Vec2>) -> Self {
+        Self { points }
+    }
+
+    /// Axis aligned rectangle centered on the origin.
+    pub fn rectangle(size: Vec2) -> Self {
+        let half = size / 2.0;
+        Self::new(vec![
+            Vec2::new(-half.x, half.y),
+            Vec2::new(half.x, half.y),
+            Vec2::new(half.x, -half.y),
+            Vec2::new(-half.x, -half.y),
+        ])
+    }
+
+    /// Loads a `.collider` file. The path is relative to the `assets` directory.
+    pub fn load(collider: &str) -> Result<Self, String> {
+        let path = Path::new(ASSET_DIRECTORY).join(collider);
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read collider {}: {}", path.display(), e))?;
+
+        let points = collider::parse(&contents)
+            .map_err(|e| format!("Could not parse collider {}: {}", path.display(), e))?;
+        if points.len() < 3 {
+            return Err(format!("Collider {} has no area", path.display()));
+        }
+
+        Ok(Self::new(points))
+    }
+
+    /// Applies a sprite transform: scale first, then rotation, then translation.
+    pub fn transformed(&self, translation: Vec2, rotation: f32, scale: f32) -> Self {
+        let (sin, cos) = rotation.sin_cos();
+        Self::new(
+            self.points
+                .iter()
+                .map(|&point| {
+                    let point = point * scale;
+                    Vec2::new(point.x * cos - point.y * sin, point.x * sin + point.y * cos)
+                        + translation
+                })
+                .collect(),
+        )
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        self.points
+            .iter()
+            .copied()
+            .zip(self.points.iter().copied().cycle().skip(1))
+    }
+
+    /// Even-odd test, which also works for concave polygons.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.edges()
+            .filter(|&(a, b)| {
+                (a.y > point.y) != (b.y > point.y)
+                    && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+            })
+            .count()
+            % 2
+            == 1
+    }
+
+    /// Point on the outline that is closest to `point`.
+    pub fn closest_point(&self, point: Vec2) -> Vec2 {
+        self.edges()
+            .map(|(a, b)| closest_point_on_segment(point, a, b))
+            .min_by(|p, q| p.distance(point).total_cmp(&q.distance(point)))
+            .unwrap_or(point)
+    }
+
+    /// Distance from `point` to the outline, negative inside, so it also tells which side of a
+    /// wall a car is on.
+    pub fn distance(&self, point: Vec2) -> f32 {
+        let distance = self.closest_point(point).distance(point);
+        if self.contains(point) {
+            -distance
+        } else {
+            distance
+        }
+    }
+
+    /// Separating axis test.
+    pub fn overlaps(&self, other: &Polygon) -> bool {
+        self.contact(other).is_some()
+    }
+
+    /// Separating axis test that also finds the shortest way out of the overlap.
+    pub fn contact(&self, other: &Polygon) -> Option<Contact> {
+        if self.points.is_empty() || other.points.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<Contact> = None;
+        for axis in self.edge_normals().chain(other.edge_normals()) {
+            let (min_a, max_a) = self.project(axis);
+            let (min_b, max_b) = other.project(axis);
+
+            // How far this polygon would have to move along the axis, in either direction.
+            let forwards = max_b - min_a;
+            let backwards = max_a - min_b;
+            if forwards < 0.0 || backwards < 0.0 {
+                return None;
+            }
+
+            let contact = if forwards < backwards {
+                Contact {
+                    normal: axis,
+                    depth: forwards,
+                }
+            } else {
+                Contact {
+                    normal: -axis,
+                    depth: backwards,
+                }
+            };
+            if best.is_none_or(|best| contact.depth < best.depth) {
+                best = Some(contact);
+            }
+        }
+
+        best
+    }
+
+    fn edge_normals(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.edges()
+            .map(|(a, b)| (b - a).perp())
+            .filter(|normal| normal.length_squared() > 0.0)
+            .map(|normal| normal.normalize())
+    }
+
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        self.points
+            .iter()
+            .map(|point| point.dot(axis))
+            .fold((f32::MAX, f32::MIN), |(min, max), value| {
+                (min.min(value), max.max(value))
+            })
+    }
+}
+
+pub fn closest_point_on_segment(point: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let segment = b - a;
+    let length_squared = segment.length_squared();
+    if length_squared == 0.0 {
+        return a;
+    }
+
+    let t = ((point - a).dot(segment) / length_squared).clamp(0.0, 1.0);
+    a + segment * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(center: Vec2, size: f32) -> Polygon {
+        Polygon::rectangle(Vec2::new(size, size)).transformed(center, 0.0, 1.0)
+    }
+
+    #[test]
+    fn contains_points_inside_only() {
+        let polygon = square(Vec2::ZERO, 10.0);
+
+        assert!(polygon.contains(Vec2::new(4.0, -4.0)));
+        assert!(!polygon.contains(Vec2::new(6.0, 0.0)));
+        assert!(!polygon.contains(Vec2::new(0.0, -6.0)));
+        assert_eq!(
+            polygon.closest_point(Vec2::new(8.0, 1.0)),
+            Vec2::new(5.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn distance_is_negative_inside() {
+        let polygon = square(Vec2::ZERO, 10.0);
+
+        assert!((polygon.distance(Vec2::new(8.0, 0.0)) - 3.0).abs() < 1e-5);
+        assert!((polygon.distance(Vec2::new(4.0, -1.0)) + 1.0).abs() < 1e-5);
+        assert!((polygon.distance(Vec2::new(8.0, 9.0)) - 5.0).abs() < 1e-5);
+        assert!(polygon.distance(Vec2::ZERO) <= 0.0);
+    }
+
+    #[test]
+    fn contact_pushes_out_along_the_shallowest_axis() {
+        let wall = square(Vec2::ZERO, 100.0);
+        let car = square(Vec2::new(0.0, 55.0), 20.0);
+
+        let contact = car.contact(&wall).unwrap();
+
+        assert!((contact.normal - Vec2::new(0.0, 1.0)).length() < 1e-5);
+        assert!((contact.depth - 5.0).abs() < 1e-4);
+        assert_eq!(wall.contact(&car).unwrap().normal, -contact.normal);
+    }
+
+    #[test]
+    fn separated_polygons_have_no_contact() {
+        let a = square(Vec2::ZERO, 10.0);
+        let b = square(Vec2::new(20.0, 0.0), 10.0);
+
+        assert_eq!(a.contact(&b), None);
+        assert!(!a.overlaps(&b));
+        assert!(a.overlaps(&square(Vec2::new(5.0, 0.0), 10.0)));
+    }
+
+    #[test]
+    fn side_is_relative_to_heading() {
+        // The obstacle is to the right of the body, in world space.
+        let contact = Contact {
+            normal: Vec2::new(-1.0, 0.0),
+            depth: 1.0,
+        };
+
+        assert_eq!(contact.side(0.0), Side::Front);
+        assert_eq!(contact.side(std::f32::consts::PI), Side::Rear);
+        assert_eq!(contact.side(std::f32::consts::FRAC_PI_2), Side::Right);
+        assert_eq!(contact.side(-std::f32::consts::FRAC_PI_2), Side::Left);
+    }
+}
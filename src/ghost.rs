@@ -0,0 +1,182 @@
+use crate::storage;
+use crate::vehicle::{CarPreset, Vehicle};
+use rusty_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+use std::fs;
+use std::path::PathBuf;
+
+/// One ghost per track lives here, in the user data directory.
+const GHOST_DIRECTORY: &str = "ghosts";
+/// Seconds of lap time between two samples of a path. Poses in between are interpolated.
+const SAMPLE_INTERVAL: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Sample {
+    /// Lap time at which the car was here.
+    pub time: f32,
+    pub position: Vec2,
+    pub rotation: f32,
+}
+
+/// Best lap driven on a track, as the path the car took.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Ghost {
+    /// Car that drove the lap.
+    pub car: CarPreset,
+    pub time: f32,
+    pub samples: Vec<Sample>,
+}
+
+impl Ghost {
+    fn path(track: &str) -> PathBuf {
+        storage::user_file(&format!("{}/{}.ron", GHOST_DIRECTORY, track))
+    }
+
+    /// Ghost of `track`, if anybody drove a lap there yet. A broken file counts as no ghost.
+    pub fn load(track: &str) -> Option<Self> {
+        let path = Self::path(track);
+        let contents = fs::read_to_string(&path).ok()?;
+        ron::from_str(&contents)
+            .map_err(|e| eprintln!("Could not parse ghost {}: {}", path.display(), e))
+            .ok()
+    }
+
+    pub fn save(&self, track: &str) -> Result<(), String> {
+        let path = Self::path(track);
+        let contents = ron::ser::to_string(self)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        storage::write(&path, &contents)
+    }
+
+    /// Where the ghost was `time` seconds into its lap, or `None` once its lap is over.
+    pub fn pose(&self, time: f32) -> Option<(Vec2, f32)> {
+        let next = self.samples.iter().position(|sample| sample.time >= time)?;
+        let to = self.samples[next];
+        let from = match next {
+            0 => return Some((to.position, to.rotation)),
+            _ => self.samples[next - 1],
+        };
+
+        let t = (time - from.time) / (to.time - from.time).max(f32::EPSILON);
+        // The short way round, so a heading going past a full turn does not spin the ghost.
+        let turn = (to.rotation - from.rotation + PI).rem_euclid(TAU) - PI;
+        Some((from.position.lerp(to.position, t), from.rotation + turn * t))
+    }
+}
+
+/// One player's current lap: the path driven so far, and how far along the ghost's path they
+/// have got.
+#[derive(Clone, Debug, Default)]
+pub struct Attempt {
+    samples: Vec<Sample>,
+    /// Ghost sample closest to the car. It only moves forward, so the start of the ghost's lap is
+    /// not mistaken for its end where the two meet at the finish line.
+    cursor: usize,
+    /// Seconds ahead of (negative) or behind (positive) the ghost at this point of the lap.
+    pub delta: Option<f32>,
+}
+
+impl Attempt {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Adds the car's pose to the path, unless the last sample is too recent.
+    pub fn record(&mut self, lap_time: f32, car: &Vehicle) {
+        if self
+            .samples
+            .last()
+            .is_some_and(|last| lap_time - last.time < SAMPLE_INTERVAL)
+        {
+            return;
+        }
+
+        self.samples.push(Sample {
+            time: lap_time,
+            position: car.position,
+            rotation: car.heading,
+        });
+    }
+
+    /// Compares the car with the ghost where the ghost went past the same spot.
+    pub fn chase(&mut self, ghost: &Ghost, lap_time: f32, car: &Vehicle) {
+        let distance = |sample: &Sample| sample.position.distance_squared(car.position);
+        while let [current, next, ..] = &ghost.samples[self.cursor.min(ghost.samples.len())..] {
+            if distance(next) > distance(current) {
+                break;
+            }
+            self.cursor += 1;
+        }
+
+        self.delta = ghost
+            .samples
+            .get(self.cursor)
+            .map(|sample| lap_time - sample.time);
+    }
+
+    /// Ends the lap where the car is now and turns its path into a ghost. The attempt starts over
+    /// for the next lap.
+    pub fn finish(&mut self, car: CarPreset, time: f32, vehicle: &Vehicle) -> Ghost {
+        let mut samples = std::mem::take(&mut self.samples);
+        samples.push(Sample {
+            time,
+            position: vehicle.position,
+            rotation: vehicle.heading,
+        });
+        self.reset();
+
+        Ghost { car, time, samples }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghost_follows_the_recorded_lap_and_measures_the_gap() {
+        // A lap straight along the x axis at 100 per second.
+        let mut car = Vehicle::new(CarPreset::Green, Vec2::ZERO, 0.0);
+        let mut attempt = Attempt::default();
+        for step in 0..=100 {
+            let time = step as f32 * 0.01;
+            car.position = Vec2::new(time * 100.0, 0.0);
+            attempt.record(time, &car);
+        }
+        let ghost = attempt.finish(CarPreset::Green, 1.0, &car);
+
+        assert!(ghost.samples.len() < 30);
+        let (position, _) = ghost.pose(0.525).unwrap();
+        assert!((position.x - 52.5).abs() < 0.01);
+        assert!(ghost.pose(1.5).is_none());
+
+        // Half a second in, but only a quarter of the way along.
+        car.position = Vec2::new(25.0, 0.0);
+        attempt.chase(&ghost, 0.5, &car);
+        assert!((attempt.delta.unwrap() - 0.25).abs() <= SAMPLE_INTERVAL);
+    }
+
+    #[test]
+    fn ghost_turns_the_short_way_round() {
+        let ghost = Ghost {
+            car: CarPreset::Green,
+            time: 1.0,
+            samples: vec![
+                Sample {
+                    time: 0.0,
+                    position: Vec2::ZERO,
+                    rotation: TAU - 0.1,
+                },
+                Sample {
+                    time: 1.0,
+                    position: Vec2::ZERO,
+                    rotation: 0.1,
+                },
+            ],
+        };
+
+        let (_, rotation) = ghost.pose(0.5).unwrap();
+        assert!((rotation - TAU).abs() < 0.001);
+    }
+}
@@ -0,0 +1,460 @@
+use crate::gamepad::{Gamepads, Pad, PadInput};
+use crate::storage;
+use crate::vehicle::Controls;
+use rusty_engine::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Written by the rebinding screen, in the user data directory.
+const BINDINGS_FILE: &str = "input.ron";
+/// Seconds the controls take to go from nothing to full, unless the bindings say otherwise.
+const RAMP_TIME: f32 = 0.1;
+/// Gamepad inputs count as pressed, for actions like Pause, once pushed this far.
+const PRESS_THRESHOLD: f32 = 0.5;
+
+/// Things a player can do, independent of the keys that do them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Action {
+    Throttle,
+    Brake,
+    SteerLeft,
+    SteerRight,
+    Pause,
+    Restart,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::Throttle,
+        Action::Brake,
+        Action::SteerLeft,
+        Action::SteerRight,
+        Action::Pause,
+        Action::Restart,
+    ];
+}
+
+/// Keys that can be bound, which is also every key the rebinding screen listens for.
+pub const KEYS: [KeyCode; 65] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Key0,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Escape,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Left,
+    KeyCode::Up,
+    KeyCode::Right,
+    KeyCode::Down,
+    KeyCode::Back,
+    KeyCode::Return,
+    KeyCode::Space,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Tab,
+    KeyCode::Comma,
+    KeyCode::Period,
+];
+
+/// Bindable key, stored in the config file by its name, for example `"Up"` or `"W"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key(pub KeyCode);
+
+impl Key {
+    pub fn name(self) -> String {
+        format!("{:?}", self.0)
+    }
+
+    pub fn from_name(name: &str) -> Option<Key> {
+        KEYS.iter()
+            .map(|&key| Key(key))
+            .find(|key| key.name() == name)
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Key::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown key {}", name)))
+    }
+}
+
+/// Keys and gamepad inputs of every action, for each local player in player order. Each player
+/// uses the gamepad with their own index, in the order the gamepads were connected.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Bindings {
+    /// Seconds the controls take to go from nothing to full. Keys are either up or down, so this
+    /// is what lets a tap steer a little instead of all the way.
+    #[serde(default = "ramp_time")]
+    pub ramp_time: f32,
+    pub players: Vec<BTreeMap<Action, Vec<Key>>>,
+    /// Files from before gamepads were supported get the default gamepad layout.
+    #[serde(default = "pad_bindings")]
+    pub pads: Vec<BTreeMap<Action, Vec<PadInput>>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let player = |keys: [KeyCode; 6]| {
+            Action::ALL
+                .iter()
+                .zip(keys)
+                .map(|(&action, key)| (action, vec![Key(key)]))
+                .collect()
+        };
+
+        Self {
+            ramp_time: RAMP_TIME,
+            players: vec![
+                player([
+                    KeyCode::Up,
+                    KeyCode::Down,
+                    KeyCode::Left,
+                    KeyCode::Right,
+                    KeyCode::Escape,
+                    KeyCode::R,
+                ]),
+                player([
+                    KeyCode::W,
+                    KeyCode::S,
+                    KeyCode::A,
+                    KeyCode::D,
+                    KeyCode::Escape,
+                    KeyCode::R,
+                ]),
+            ],
+            pads: pad_bindings(),
+        }
+    }
+}
+
+fn ramp_time() -> f32 {
+    RAMP_TIME
+}
+
+/// Triggers for throttle and brake and the left stick for steering, the same for every player.
+fn pad_bindings() -> Vec<BTreeMap<Action, Vec<PadInput>>> {
+    let pad = [
+        (
+            Action::Throttle,
+            vec![PadInput::RightTrigger, PadInput::South],
+        ),
+        (Action::Brake, vec![PadInput::LeftTrigger, PadInput::West]),
+        (
+            Action::SteerLeft,
+            vec![PadInput::LeftStickLeft, PadInput::DPadLeft],
+        ),
+        (
+            Action::SteerRight,
+            vec![PadInput::LeftStickRight, PadInput::DPadRight],
+        ),
+        (Action::Pause, vec![PadInput::Start]),
+        (Action::Restart, vec![PadInput::Select]),
+    ];
+    vec![pad.iter().cloned().collect(); 2]
+}
+
+impl Bindings {
+    /// Reads `BINDINGS_FILE`, or falls back to the default keys if nobody rebound anything yet.
+    pub fn load() -> Result<Self, String> {
+        let path = storage::user_file(BINDINGS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read bindings {}: {}", path.display(), e))?;
+        Self::parse(&contents)
+            .map_err(|e| format!("Could not parse bindings {}: {}", path.display(), e))
+    }
+
+    /// Local players the file has no bindings for get the default ones, so nobody is left
+    /// without controls.
+    fn parse(contents: &str) -> ron::Result<Self> {
+        let mut bindings: Self = ron::from_str(contents)?;
+        let default = Self::default();
+        let players = bindings.players.len();
+        bindings
+            .players
+            .extend(default.players.into_iter().skip(players));
+        let pads = bindings.pads.len();
+        bindings.pads.extend(default.pads.into_iter().skip(pads));
+        Ok(bindings)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save(&storage::user_file(BINDINGS_FILE), self)
+    }
+
+    pub fn keys(&self, player: usize, action: Action) -> &[Key] {
+        self.players
+            .get(player)
+            .and_then(|actions| actions.get(&action))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn pad_inputs(&self, player: usize, action: Action) -> &[PadInput] {
+        self.pads
+            .get(player)
+            .and_then(|actions| actions.get(&action))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces the keys of one action with a single key.
+    pub fn bind(&mut self, player: usize, action: Action, key: Key) {
+        if let Some(actions) = self.players.get_mut(player) {
+            actions.insert(action, vec![key]);
+        }
+    }
+
+    /// Replaces the gamepad inputs of one action with a single input.
+    pub fn bind_pad(&mut self, player: usize, action: Action, input: PadInput) {
+        if self.pads.len() <= player {
+            self.pads.resize(player + 1, BTreeMap::new());
+        }
+        self.pads[player].insert(action, vec![input]);
+    }
+
+    /// How much of `action` `player` asks for right now, between 0 and 1. A key is all or
+    /// nothing, a trigger or stick counts as far as it is pushed.
+    pub fn value(
+        &self,
+        keyboard: &KeyboardState,
+        pad: Option<&Pad>,
+        player: usize,
+        action: Action,
+    ) -> f32 {
+        let pressed = self
+            .keys(player, action)
+            .iter()
+            .any(|key| keyboard.pressed(key.0));
+        let key = if pressed { 1.0 } else { 0.0 };
+        pad.map_or(0.0, |pad| self.pad_value(pad, player, action))
+            .max(key)
+    }
+
+    fn pad_value(&self, pad: &Pad, player: usize, action: Action) -> f32 {
+        self.pad_inputs(player, action)
+            .iter()
+            .map(|&input| pad.value(input))
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Turns bindings into driving controls, easing them towards what the keys and gamepads ask for.
+#[derive(Clone, Debug, Default)]
+pub struct Input {
+    pub bindings: Bindings,
+    gamepads: Gamepads,
+    /// Gamepads as of this frame and the one before, to tell when something was just pressed.
+    pads: Vec<Pad>,
+    previous_pads: Vec<Pad>,
+    controls: Vec<Controls>,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings, gamepads: Gamepads) -> Self {
+        Self {
+            bindings,
+            gamepads,
+            pads: Vec::new(),
+            previous_pads: Vec::new(),
+            controls: Vec::new(),
+        }
+    }
+
+    /// Reads the gamepads for this frame. Called once per frame, before anything asks for input.
+    pub fn poll(&mut self) {
+        self.previous_pads = std::mem::replace(&mut self.pads, self.gamepads.pads());
+    }
+
+    /// Whether any player's key or gamepad input for `action` went down this frame.
+    pub fn just_pressed(&self, keyboard: &KeyboardState, action: Action) -> bool {
+        let bindings = &self.bindings;
+        let keys = (0..bindings.players.len()).any(|player| {
+            bindings
+                .keys(player, action)
+                .iter()
+                .any(|key| keyboard.just_pressed(key.0))
+        });
+        let pads = (0..self.pads.len()).any(|player| {
+            bindings
+                .pad_inputs(player, action)
+                .iter()
+                .any(|&input| self.pad_just_pressed(player, input))
+        });
+        keys || pads
+    }
+
+    /// First input of `player`'s gamepad that went down this frame, for the rebinding screen.
+    pub fn first_pad_press(&self, player: usize) -> Option<PadInput> {
+        PadInput::ALL
+            .into_iter()
+            .find(|&input| self.pad_just_pressed(player, input))
+    }
+
+    fn pad_just_pressed(&self, player: usize, input: PadInput) -> bool {
+        let value = |pads: &[Pad]| pads.get(player).map_or(0.0, |pad| pad.value(input));
+        value(&self.pads) >= PRESS_THRESHOLD && value(&self.previous_pads) < PRESS_THRESHOLD
+    }
+
+    /// Controls of every bound player for this frame.
+    pub fn controls(&mut self, keyboard: &KeyboardState, delta: f32) -> &[Controls] {
+        let bindings = &self.bindings;
+        self.controls
+            .resize(bindings.players.len(), Controls::default());
+
+        for (player, controls) in self.controls.iter_mut().enumerate() {
+            let pad = self.pads.get(player);
+            let value = |action| bindings.value(keyboard, pad, player, action);
+            let target = Controls {
+                throttle: value(Action::Throttle),
+                brake: value(Action::Brake),
+                steering: value(Action::SteerLeft) - value(Action::SteerRight),
+            };
+            *controls = ease(*controls, target, delta, bindings.ramp_time);
+        }
+
+        &self.controls
+    }
+
+    /// Lets go of everything, so the next race does not start with the controls of the last one.
+    pub fn release(&mut self) {
+        self.controls.clear();
+    }
+}
+
+/// Moves every control towards `target` at a rate that covers the full range in `ramp_time`.
+fn ease(current: Controls, target: Controls, delta: f32, ramp_time: f32) -> Controls {
+    if ramp_time <= 0.0 {
+        return target;
+    }
+
+    let step = delta / ramp_time;
+    let towards = |from: f32, to: f32| from + (to - from).clamp(-step, step);
+    Controls {
+        throttle: towards(current.throttle, target.throttle),
+        brake: towards(current.brake, target.brake),
+        steering: towards(current.steering, target.steering),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip_through_the_config_format() {
+        let bindings = Bindings::default();
+        let text = ron::ser::to_string_pretty(&bindings, Default::default()).unwrap();
+
+        assert!(text.contains("\"Up\""));
+        assert_eq!(ron::from_str::<Bindings>(&text).unwrap(), bindings);
+        assert!(ron::from_str::<Bindings>("(players: [{Throttle: [\"Nope\"]}])").is_err());
+
+        let without_ramp = Bindings::parse("(players: [])").unwrap();
+        assert_eq!(without_ramp, Bindings::default());
+    }
+
+    #[test]
+    fn missing_players_get_the_default_bindings() {
+        let one_player = Bindings::parse("(players: [{Throttle: [\"Space\"]}], pads: [])").unwrap();
+        let default = Bindings::default();
+
+        assert_eq!(one_player.keys(0, Action::Throttle), &[Key(KeyCode::Space)]);
+        assert!(one_player.keys(0, Action::Brake).is_empty());
+        assert_eq!(one_player.players[1], default.players[1]);
+        assert_eq!(one_player.pads, default.pads);
+    }
+
+    #[test]
+    fn controls_ramp_towards_the_keys() {
+        let full = Controls {
+            throttle: 1.0,
+            brake: 0.0,
+            steering: -1.0,
+        };
+
+        let eased = ease(Controls::default(), full, 0.05, 0.1);
+        assert_eq!(eased.throttle, 0.5);
+        assert_eq!(eased.steering, -0.5);
+        assert_eq!(ease(eased, full, 0.05, 0.1), full);
+        assert_eq!(ease(Controls::default(), full, 0.01, 0.0), full);
+    }
+
+    #[test]
+    fn triggers_and_sticks_drive_proportionally() {
+        let mut input = Input::new(Bindings::default(), Gamepads::default());
+        let keyboard = KeyboardState::default();
+        let mut pad = Pad::default();
+        pad.set(PadInput::RightTrigger, 0.4);
+        pad.set(PadInput::LeftStickLeft, 0.25);
+        input.pads = vec![pad];
+
+        let controls = input.controls(&keyboard, 1.0)[0];
+        assert_eq!(controls.throttle, 0.4);
+        assert_eq!(controls.steering, 0.25);
+        assert!(!input.just_pressed(&keyboard, Action::Pause));
+
+        pad.set(PadInput::Start, 1.0);
+        input.previous_pads = std::mem::replace(&mut input.pads, vec![pad]);
+        assert!(input.just_pressed(&keyboard, Action::Pause));
+        assert_eq!(input.first_pad_press(0), Some(PadInput::Start));
+        assert_eq!(input.first_pad_press(1), None);
+    }
+}
@@ -1,220 +1,1246 @@
+mod ai;
+mod camera;
+mod collider;
+mod combo;
+mod difficulty;
+mod enemy;
+mod events;
+mod gamepad;
+mod geometry;
+mod ghost;
+mod input;
+mod pickup;
+mod race;
+mod replay;
+mod scores;
+mod settings;
+mod sim;
+mod storage;
+mod timestep;
+mod track;
+mod vehicle;
+
+use camera::Camera;
+use difficulty::Difficulty;
+use events::GameEvent;
+use gamepad::Gamepads;
+use geometry::Side;
+use ghost::{Attempt, Ghost};
+use input::{Action, Bindings, Input, Key, KEYS};
+use race::ordinal;
 use rand::prelude::*;
+use replay::{Playback, Replay};
+use rusty_engine::prelude::bevy::window::PresentMode;
 use rusty_engine::prelude::*;
+use scores::HighScores;
+use settings::{Setting, Settings};
+use sim::{Simulation, CAR_SCALE};
+use std::collections::HashMap;
 use std::default::Default;
+use std::path::{Path, PathBuf};
+use timestep::{FixedTimestep, TIMESTEP};
+use track::{Track, DEFAULT_TRACK};
+use vehicle::{CarPreset, Vehicle};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Phase {
+    #[default]
+    Menu,
+    Countdown,
+    Racing,
+    Paused,
+    GameOver,
+    Finished,
+    Bindings,
+    Leaderboard,
+    Options,
+    /// A replay that stopped before the race was over, like one of a restarted run.
+    ReplayOver,
+}
 
-#[derive(Default)]
-struct Enemy {
-    label: String,
-    position: Vec2,
-    direction: f32,
-    amplitude: f32,
+/// Row of the rebinding screen that is selected, and whether it is waiting for a key.
+#[derive(Clone, Copy, Debug, Default)]
+struct Rebinding {
+    row: usize,
+    capturing: bool,
 }
 
-#[derive(Default)]
 struct GameState {
-    health: f32,
-    direction: f32,
-    speed: f32,
-    score: i32,
-    spawn_timer: Timer,
-    player_hit: bool,
-    enemies: Vec<Enemy>,
+    phase: Phase,
+    countdown: Timer,
+    sim: Simulation,
+    timestep: FixedTimestep,
+    /// Position and rotation of every car and enemy before the most recent step, by label, for
+    /// drawing them in between steps.
+    previous_poses: HashMap<String, (Vec2, f32)>,
+    camera: Camera,
+    input: Input,
+    rebinding: Rebinding,
+    settings: Settings,
+    /// Row of the options menu that is selected.
+    options_row: usize,
+    high_scores: HighScores,
+    /// Players whose run made the high score table and who still have to enter their initials,
+    /// in player order.
+    pending_initials: Vec<usize>,
+    initials: String,
+    /// Everything random in the race comes from here, seeded at the start of each run so the run
+    /// can be replayed.
+    rng: StdRng,
+    /// Input of the current run, written to disk when the run ends.
+    recording: Replay,
+    /// Replay being watched instead of taking input from the keyboard.
+    playback: Option<Playback>,
+    /// Best lap on this track, for the players to race against.
+    ghost: Option<Ghost>,
+    /// Lap each player is driving right now, in player order.
+    attempts: Vec<Attempt>,
+    /// Where F5 last kept the run, shown until the next run starts.
+    kept_replay: Option<PathBuf>,
+    /// Labels of the score popups on screen, with where in the world they were scored and how
+    /// long each has been up.
+    popups: Vec<(String, Vec2, f32)>,
+    next_popup_id: u32,
 }
 
 fn main() {
+    // Broken settings should not keep anybody from playing either.
+    let settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("{}, using the default settings", e);
+        Settings::default()
+    });
+
     let mut game = Game::new();
     game.window_settings(WindowDescriptor {
         title: "Boring Game".into(),
-        width: 1920.0,
-        height: 1080.0,
+        width: settings.resolution.0 as f32,
+        height: settings.resolution.1 as f32,
+        present_mode: if settings.vsync {
+            PresentMode::Fifo
+        } else {
+            PresentMode::Immediate
+        },
+        mode: if settings.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        },
         ..Default::default()
     });
 
-    let track_inner_sprite = game.add_sprite("track_inner", "track/track01.png");
-    track_inner_sprite.collision = true;
+    // Either a track name, or `--replay <file>` to watch a recorded run on its own track.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let playback = match args.as_slice() {
+        [flag, path] if flag == "--replay" => match Replay::load(Path::new(path)) {
+            Ok(replay) => Some(Playback::new(replay)),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    let (track_name, cars) = match &playback {
+        Some(playback) => (
+            playback.replay.track.clone(),
+            playback.replay.players.clone(),
+        ),
+        None => (
+            args.first()
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_TRACK.to_string()),
+            PLAYER_CARS[..1].to_vec(),
+        ),
+    };
+    let sim = match Track::load(&track_name).and_then(|track| Simulation::new(track, &cars)) {
+        Ok(sim) => sim,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // A broken bindings file should not keep anybody from playing.
+    let bindings = Bindings::load().unwrap_or_else(|e| {
+        eprintln!("{}, using the default keys", e);
+        Bindings::default()
+    });
+
+    let track_inner_sprite = game.add_sprite("track_inner", sim.track.inner.image.as_str());
     track_inner_sprite.layer = 0.0;
 
-    let track_outer_sprite = game.add_sprite("track_outer", "track/track01_outer.png");
-    track_outer_sprite.collision = true;
+    let track_outer_sprite = game.add_sprite("track_outer", sim.track.outer.image.as_str());
     track_outer_sprite.layer = 0.0;
 
-    let player_sprite = game.add_sprite("player", SpritePreset::RacingCarGreen);
-    player_sprite.scale = 0.5;
-    player_sprite.translation = Vec2::new(0.0, 300.0);
-    player_sprite.collision = true;
-    player_sprite.layer = 100.0;
+    let message_text = game.add_text("message", "");
+    message_text.font_size = 60.0;
 
-    let _ = game.add_text("speed", "");
-    let _ = game.add_text("score", "");
-    let _ = game.add_text("health", "");
+    // Full screen lists, like the controls and the leaderboard.
+    let _ = game.add_text("screen", "");
 
-    game.audio_manager
-        .play_music(MusicPreset::WhimsicalPopsicle, 0.1);
+    game.audio_manager.play_music(MUSIC, settings.music());
 
-    game.add_logic(player_movement_logic);
-    game.add_logic(enemy_movement_logic);
-    game.add_logic(collision_logic);
-    game.add_logic(scoring_logic);
-    game.add_logic(enemy_spawn_logic);
+    game.add_logic(phase_logic);
+    game.add_logic(simulation_logic);
+    game.add_logic(ghost_logic);
+    game.add_logic(camera_logic);
+    game.add_logic(sprite_logic);
+    game.add_logic(sound_logic);
+    game.add_logic(popup_logic);
     game.add_logic(hud_logic);
 
-    let initial_game_state = GameState {
-        health: 100.0,
-        spawn_timer: Timer::from_seconds(0.0, false),
-        player_hit: false,
-        enemies: vec![
-            Enemy {
-                label: "enemy_1".to_string(),
-                position: Vec2::new(-150.0, 300.0),
-                direction: UP,
-                amplitude: 20.0,
-            },
-            Enemy {
-                label: "enemy_2".to_string(),
-                position: Vec2::new(0.0, -300.0),
-                direction: LEFT,
-                amplitude: 50.0,
-            },
-        ],
-        ..Default::default()
+    let camera = Camera::new(sim.track.start.position);
+    let mut game_state = GameState {
+        phase: Phase::Menu,
+        countdown: Timer::from_seconds(0.0, false),
+        sim,
+        timestep: FixedTimestep::default(),
+        previous_poses: HashMap::new(),
+        camera,
+        input: Input::new(bindings, Gamepads::start()),
+        rebinding: Rebinding::default(),
+        settings,
+        options_row: 0,
+        high_scores: HighScores::load(),
+        pending_initials: Vec::new(),
+        initials: String::new(),
+        rng: StdRng::seed_from_u64(0),
+        recording: Replay::default(),
+        playback,
+        ghost: Ghost::load(&track_name),
+        attempts: Vec::new(),
+        kept_replay: None,
+        popups: Vec::new(),
+        next_popup_id: 1,
     };
+    if game_state.playback.is_some() {
+        start_run(&mut game_state);
+    }
+
+    game.run(game_state);
+}
+
+const MUSIC: MusicPreset = MusicPreset::WhimsicalPopsicle;
+const COUNTDOWN_SECONDS: f32 = 3.0;
+
+const MAX_INITIALS: usize = 3;
+
+/// Seconds a score popup stays up, and how fast it floats upwards meanwhile.
+const POPUP_SECONDS: f32 = 1.0;
+const POPUP_RISE: f32 = 60.0;
+
+/// Car of each local player, in player order.
+const PLAYER_CARS: [CarPreset; 2] = [CarPreset::Green, CarPreset::Red];
+
+fn phase_logic(engine: &mut Engine, game_state: &mut GameState) {
+    game_state.input.poll();
+    let keyboard = &engine.keyboard_state;
+    let input = &game_state.input;
+
+    match game_state.phase {
+        Phase::Menu => {
+            if keyboard.just_pressed_any(&[KeyCode::Return, KeyCode::Key1]) {
+                choose_players(engine, game_state, 1);
+            } else if keyboard.just_pressed(KeyCode::Key2) {
+                choose_players(engine, game_state, 2);
+            } else if keyboard.just_pressed(KeyCode::B) {
+                game_state.rebinding = Rebinding::default();
+                game_state.phase = Phase::Bindings;
+            } else if keyboard.just_pressed(KeyCode::L) {
+                game_state.phase = Phase::Leaderboard;
+            } else if keyboard.just_pressed(KeyCode::O) {
+                game_state.options_row = 0;
+                game_state.phase = Phase::Options;
+            } else if keyboard.just_pressed(KeyCode::P) {
+                watch_latest_replay(engine, game_state);
+            }
+        }
+        Phase::Countdown => {
+            if game_state.countdown.tick(engine.delta).just_finished() {
+                game_state.phase = Phase::Racing;
+                engine
+                    .audio_manager
+                    .play_sfx(SfxPreset::Jingle2, game_state.settings.sfx());
+            }
+        }
+        Phase::Racing => {
+            let sim = &game_state.sim;
+            if sim.is_over() {
+                // The race is only lost when nobody made it to the flag.
+                if sim
+                    .players
+                    .iter()
+                    .any(|player| player.finish_time.is_some())
+                {
+                    game_state.phase = Phase::Finished;
+                } else {
+                    game_state.phase = Phase::GameOver;
+                    engine
+                        .audio_manager
+                        .play_sfx(SfxPreset::Jingle3, game_state.settings.sfx());
+                }
+                if game_state.playback.is_none() {
+                    save_recording(game_state, &Replay::latest_path());
+                    ask_for_initials(game_state);
+                }
+            } else if input.just_pressed(keyboard, Action::Pause) {
+                game_state.phase = Phase::Paused;
+            } else if input.just_pressed(keyboard, Action::Restart) {
+                reset_game(engine, game_state);
+            }
+        }
+        Phase::Paused => {
+            if input.just_pressed(keyboard, Action::Pause) {
+                game_state.phase = Phase::Racing;
+            } else if input.just_pressed(keyboard, Action::Restart) {
+                reset_game(engine, game_state);
+            } else if keyboard.just_pressed(KeyCode::F5) {
+                keep_recording(game_state);
+            }
+        }
+        Phase::GameOver | Phase::Finished if !game_state.pending_initials.is_empty() => {
+            initials_logic(engine, game_state)
+        }
+        Phase::GameOver | Phase::Finished | Phase::ReplayOver if game_state.playback.is_some() => {
+            if keyboard.just_pressed(KeyCode::Return) {
+                reset_game(engine, game_state);
+            } else if keyboard.just_pressed(KeyCode::Escape) {
+                game_state.playback = None;
+                game_state.phase = Phase::Menu;
+            }
+        }
+        Phase::GameOver | Phase::Finished | Phase::ReplayOver => {
+            if keyboard.just_pressed(KeyCode::Return)
+                || input.just_pressed(keyboard, Action::Restart)
+            {
+                reset_game(engine, game_state);
+            } else if keyboard.just_pressed(KeyCode::F5) {
+                keep_recording(game_state);
+            }
+        }
+        Phase::Bindings => rebinding_logic(engine, game_state),
+        Phase::Options => options_logic(engine, game_state),
+        Phase::Leaderboard => {
+            if keyboard.just_pressed_any(&[KeyCode::Escape, KeyCode::Return]) {
+                game_state.phase = Phase::Menu;
+            }
+        }
+    }
+}
+
+/// Lines up everybody whose run is good enough for the high score table.
+fn ask_for_initials(game_state: &mut GameState) {
+    let sim = &game_state.sim;
+    game_state.pending_initials = sim
+        .players
+        .iter()
+        .enumerate()
+        .filter(|(_, player)| {
+            game_state.high_scores.qualifies(
+                &sim.track.name,
+                player.preset,
+                player.score,
+                player.best_lap,
+                sim.difficulty,
+            )
+        })
+        .map(|(i, _)| i)
+        .collect();
+    game_state.initials.clear();
+}
+
+/// Letters type the initials, Backspace takes one back and Enter records them. The table is
+/// saved once the last player is done.
+fn initials_logic(engine: &mut Engine, game_state: &mut GameState) {
+    let keyboard = &engine.keyboard_state;
+
+    if keyboard.just_pressed(KeyCode::Back) {
+        game_state.initials.pop();
+    } else if keyboard.just_pressed(KeyCode::Return) && !game_state.initials.is_empty() {
+        let player = &game_state.sim.players[game_state.pending_initials.remove(0)];
+        game_state.high_scores.record(
+            &game_state.sim.track.name,
+            player.preset,
+            &game_state.initials,
+            player.score,
+            player.best_lap,
+            game_state.sim.difficulty,
+        );
+        game_state.initials.clear();
+        engine
+            .audio_manager
+            .play_sfx(SfxPreset::Confirmation2, game_state.settings.sfx());
+
+        if game_state.pending_initials.is_empty() {
+            if let Err(e) = game_state.high_scores.save() {
+                eprintln!("{}", e);
+            }
+        }
+    } else if game_state.initials.len() < MAX_INITIALS {
+        let letter = KEYS
+            .iter()
+            .map(|&key| Key(key).name())
+            .zip(KEYS)
+            .find(|(name, key)| {
+                name.len() == 1
+                    && name.chars().all(|c| c.is_ascii_uppercase())
+                    && keyboard.just_pressed(*key)
+            });
+        if let Some((name, _)) = letter {
+            game_state.initials += &name;
+        }
+    }
+}
+
+/// Up and down pick an action, Enter waits for the key or gamepad input to bind to it, and
+/// Backspace cancels the wait. Escape saves the bindings and goes back to the menu, unless it is
+/// the key being bound, so it can always be given back to Pause.
+fn rebinding_logic(engine: &mut Engine, game_state: &mut GameState) {
+    let keyboard = &engine.keyboard_state;
+    let rebinding = &mut game_state.rebinding;
+    let (player, action) = (
+        rebinding.row / Action::ALL.len(),
+        Action::ALL[rebinding.row % Action::ALL.len()],
+    );
+    let pad_press = game_state.input.first_pad_press(player);
+    let bindings = &mut game_state.input.bindings;
+    let rows = bindings.players.len() * Action::ALL.len();
 
-    game.run(initial_game_state);
+    if rebinding.capturing {
+        if keyboard.just_pressed(KeyCode::Back) {
+            rebinding.capturing = false;
+        } else if let Some(&key) = KEYS.iter().find(|&&key| keyboard.just_pressed(key)) {
+            bindings.bind(player, action, Key(key));
+            rebinding.capturing = false;
+            engine
+                .audio_manager
+                .play_sfx(SfxPreset::Click, game_state.settings.sfx());
+        } else if let Some(pad_input) = pad_press {
+            bindings.bind_pad(player, action, pad_input);
+            rebinding.capturing = false;
+            engine
+                .audio_manager
+                .play_sfx(SfxPreset::Click, game_state.settings.sfx());
+        }
+    } else if rows > 0 && keyboard.just_pressed(KeyCode::Up) {
+        rebinding.row = (rebinding.row + rows - 1) % rows;
+    } else if rows > 0 && keyboard.just_pressed(KeyCode::Down) {
+        rebinding.row = (rebinding.row + 1) % rows;
+    } else if rows > 0 && keyboard.just_pressed(KeyCode::Return) {
+        rebinding.capturing = true;
+    } else if keyboard.just_pressed(KeyCode::Escape) {
+        if let Err(e) = bindings.save() {
+            eprintln!("{}", e);
+        }
+        game_state.phase = Phase::Menu;
+    }
 }
 
-const ACCELERATION: f32 = 10.0;
-const ROTATION_SPEED: f32 = 5.0;
+/// Up and down pick a setting and left and right change it. Escape saves the settings and goes
+/// back to the menu. Volumes apply right away, so the music restarts at the new volume.
+fn options_logic(engine: &mut Engine, game_state: &mut GameState) {
+    let keyboard = &engine.keyboard_state;
+    let settings = &mut game_state.settings;
+    let rows = Setting::ALL.len();
+    let setting = Setting::ALL[game_state.options_row];
 
-fn player_movement_logic(engine: &mut Engine, game_state: &mut GameState) {
-    let player = engine.sprites.get_mut("player").unwrap();
+    if keyboard.just_pressed(KeyCode::Up) {
+        game_state.options_row = (game_state.options_row + rows - 1) % rows;
+    } else if keyboard.just_pressed(KeyCode::Down) {
+        game_state.options_row = (game_state.options_row + 1) % rows;
+    } else if keyboard.just_pressed_any(&[KeyCode::Left, KeyCode::Right]) {
+        let step = if keyboard.just_pressed(KeyCode::Left) {
+            -1
+        } else {
+            1
+        };
+        settings.change(setting, step);
 
-    if engine.keyboard_state.pressed(KeyCode::Up) {
-        game_state.speed += ACCELERATION;
+        if matches!(setting, Setting::MasterVolume | Setting::MusicVolume) {
+            engine.audio_manager.stop_music();
+            engine.audio_manager.play_music(MUSIC, settings.music());
+        }
+        engine
+            .audio_manager
+            .play_sfx(SfxPreset::Click, settings.sfx());
+    } else if keyboard.just_pressed(KeyCode::Escape) {
+        if let Err(e) = settings.save() {
+            eprintln!("{}", e);
+        }
+        game_state.phase = Phase::Menu;
     }
-    if engine.keyboard_state.pressed(KeyCode::Down) {
-        game_state.speed -= ACCELERATION;
+}
+
+/// Sets the race up for `count` local players and starts the countdown.
+fn choose_players(engine: &mut Engine, game_state: &mut GameState, count: usize) {
+    if set_up_cars(engine, game_state, &PLAYER_CARS[..count]) {
+        start_run(game_state);
     }
-    if engine.keyboard_state.pressed(KeyCode::Left) {
-        game_state.direction += ROTATION_SPEED * engine.delta_f32;
+}
+
+/// Gives the race one player per car in `cars`. The car sprites are dropped when the field of
+/// cars changes, so `sprite_logic` recreates them. Returns false if the race could not be set up.
+fn set_up_cars(engine: &mut Engine, game_state: &mut GameState, cars: &[CarPreset]) -> bool {
+    let current: Vec<CarPreset> = game_state.sim.players.iter().map(|p| p.preset).collect();
+    if current == cars {
+        game_state.sim.restart();
+        return true;
     }
-    if engine.keyboard_state.pressed(KeyCode::Right) {
-        game_state.direction -= ROTATION_SPEED * engine.delta_f32;
+
+    match Simulation::new(game_state.sim.track.clone(), cars) {
+        Ok(sim) => game_state.sim = sim,
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
     }
+    engine.sprites.retain(|label, _| {
+        !["player_", "rival_", "ghost_"]
+            .iter()
+            .any(|p| label.starts_with(p))
+    });
+    true
+}
 
-    player.rotation = game_state.direction;
+/// Watches the run written when the last race ended. Replays of other tracks have to be opened
+/// with `--replay`, since the track is only loaded at startup.
+fn watch_latest_replay(engine: &mut Engine, game_state: &mut GameState) {
+    let replay = match Replay::load(&Replay::latest_path()) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    if replay.track != game_state.sim.track.name {
+        eprintln!(
+            "The last replay is on {}, start the game with --replay to watch it",
+            replay.track
+        );
+        return;
+    }
+
+    if set_up_cars(engine, game_state, &replay.players) {
+        game_state.playback = Some(Playback::new(replay));
+        start_run(game_state);
+    }
+}
+
+/// Seeds the random number generator for a new run and starts recording it, or starts the
+/// replay over when watching one.
+fn start_run(game_state: &mut GameState) {
+    let seed = match &mut game_state.playback {
+        Some(playback) => {
+            playback.rewind();
+            playback.replay.seed
+        }
+        None => thread_rng().gen(),
+    };
+    game_state.rng = StdRng::seed_from_u64(seed);
+    let difficulty = match &game_state.playback {
+        Some(playback) => playback.replay.difficulty,
+        None => game_state.settings.difficulty,
+    };
+    game_state.sim.difficulty = difficulty;
+    game_state.attempts = vec![Attempt::default(); game_state.sim.players.len()];
+    game_state.kept_replay = None;
+    game_state.timestep.reset();
+    game_state.previous_poses.clear();
+    game_state.camera = Camera::new(game_state.sim.track.start.position);
+    game_state.recording = Replay::new(
+        &game_state.sim.track.name,
+        game_state.sim.players.iter().map(|p| p.preset).collect(),
+        seed,
+        difficulty,
+    );
 
-    player.translation.x += game_state.speed * engine.delta_f32 * game_state.direction.cos();
-    player.translation.y += game_state.speed * engine.delta_f32 * game_state.direction.sin();
+    start_countdown(game_state);
 }
 
-fn enemy_movement_logic(engine: &mut Engine, game_state: &mut GameState) {
-    let time = engine.time_since_startup_f64;
+fn start_countdown(game_state: &mut GameState) {
+    game_state.countdown = Timer::from_seconds(COUNTDOWN_SECONDS, false);
+    game_state.phase = Phase::Countdown;
+}
+
+/// Throws away the current run and starts a new countdown on the same track. Sprites of enemies
+/// that no longer exist are cleaned up by `sprite_logic`.
+fn reset_game(engine: &mut Engine, game_state: &mut GameState) {
+    // A run given up halfway can still be worth watching.
+    if game_state.playback.is_none() && matches!(game_state.phase, Phase::Racing | Phase::Paused) {
+        save_recording(game_state, &Replay::latest_path());
+    }
+
+    game_state.sim.restart();
+    game_state.input.release();
+
+    engine.audio_manager.stop_music();
+    engine
+        .audio_manager
+        .play_music(MUSIC, game_state.settings.music());
+
+    start_run(game_state);
+}
+
+fn save_recording(game_state: &GameState, path: &Path) {
+    if let Err(e) = game_state.recording.save(path) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Copies the current run, or the replay being watched, next to the latest one under a name
+/// that will not be overwritten, for sharing or attaching to a bug report.
+fn keep_recording(game_state: &mut GameState) {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let path = Replay::latest_path().with_file_name(format!("replay_{}.ron", seconds));
+    let replay = match &game_state.playback {
+        Some(playback) => &playback.replay,
+        None => &game_state.recording,
+    };
+    match replay.save(&path) {
+        Ok(()) => game_state.kept_replay = Some(path),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+fn simulation_logic(engine: &mut Engine, game_state: &mut GameState) {
+    if game_state.phase != Phase::Racing {
+        for player in &mut game_state.sim.players {
+            player.events.clear();
+        }
+        return;
+    }
+
+    // The keys are read once per frame and held for every step of it.
+    let controls = game_state
+        .input
+        .controls(&engine.keyboard_state, engine.delta_f32)
+        .to_vec();
+    let steps = game_state.timestep.steps(engine.delta_f32);
+
+    // Each step replaces the events of the one before, so they are gathered up for the logic
+    // that runs after the simulation this frame.
+    let mut events = vec![Vec::new(); game_state.sim.players.len()];
+    for _ in 0..steps {
+        // Stopping right at the end keeps recordings and their playback the same length.
+        if game_state.sim.is_over() {
+            break;
+        }
+
+        let (delta, controls) = match &mut game_state.playback {
+            Some(playback) => match playback.next_frame() {
+                Some(frame) => frame,
+                None => {
+                    game_state.phase = Phase::ReplayOver;
+                    break;
+                }
+            },
+            None => {
+                game_state.recording.record(TIMESTEP, &controls);
+                (TIMESTEP, controls.as_slice())
+            }
+        };
+
+        game_state.previous_poses = poses(&game_state.sim);
+        game_state.sim.step(controls, delta, &mut game_state.rng);
+        for (events, player) in events.iter_mut().zip(&mut game_state.sim.players) {
+            events.append(&mut player.events);
+        }
+    }
+
+    for (player, events) in game_state.sim.players.iter_mut().zip(events) {
+        player.events = events;
+    }
+}
 
-    for enemy in &mut game_state.enemies {
+/// Where every car and enemy is, by sprite label.
+fn poses(sim: &Simulation) -> HashMap<String, (Vec2, f32)> {
+    let players = sim.players.iter().map(|player| {
+        (
+            player.label.clone(),
+            (player.car.position, player.car.heading),
+        )
+    });
+    let rivals = sim
+        .rivals
+        .iter()
+        .map(|rival| (rival.label.clone(), (rival.car.position, rival.car.heading)));
+    let enemies = sim
+        .enemies
+        .iter()
+        .map(|enemy| (enemy.label.clone(), (enemy.translation, 0.0)));
+
+    players.chain(rivals).chain(enemies).collect()
+}
+
+/// Follows every player's lap and compares it with the ghost. A lap that beats the ghost
+/// becomes the new ghost, unless it is only being watched in a replay.
+fn ghost_logic(_engine: &mut Engine, game_state: &mut GameState) {
+    if game_state.phase != Phase::Racing {
+        return;
+    }
+
+    let track = &game_state.sim.track.name;
+    for (player, attempt) in game_state.sim.players.iter().zip(&mut game_state.attempts) {
+        for event in &player.events {
+            if let GameEvent::LapCompleted { time } = *event {
+                let lap = attempt.finish(player.preset, time, &player.car);
+                if game_state.playback.is_none()
+                    && game_state
+                        .ghost
+                        .as_ref()
+                        .is_none_or(|ghost| time < ghost.time)
+                {
+                    if let Err(e) = lap.save(track) {
+                        eprintln!("{}", e);
+                    }
+                    game_state.ghost = Some(lap);
+                }
+            }
+        }
+
+        if player.is_done() {
+            attempt.delta = None;
+            continue;
+        }
+        attempt.record(player.lap_time, &player.car);
+        if let Some(ghost) = &game_state.ghost {
+            attempt.chase(ghost, player.lap_time, &player.car);
+        }
+    }
+}
+
+/// Follows the players still racing, or all of them once nobody is, and shakes the screen when
+/// one of them hits the wall.
+fn camera_logic(engine: &mut Engine, game_state: &mut GameState) {
+    let sim = &game_state.sim;
+    let racing: Vec<_> = sim.players.iter().filter(|p| !p.is_done()).collect();
+    let followed = if racing.is_empty() {
+        sim.players.iter().collect()
+    } else {
+        racing
+    };
+    let cars: Vec<(Vec2, Vec2)> = followed
+        .iter()
+        .map(|player| (player.car.position, player.car.velocity))
+        .collect();
+
+    let camera = &mut game_state.camera;
+    for event in followed.iter().flat_map(|player| &player.events) {
+        if let GameEvent::WallHit { impact, .. } = *event {
+            camera.shake(impact);
+        }
+    }
+    camera.update(engine.delta_f32, &cars, engine.window_dimensions);
+}
+
+/// Mirrors the simulation onto the engine sprites. Cars and enemies come and go with the
+/// simulation, so their sprites are created on demand and dropped once they are gone. Everything
+/// is drawn part of the way between the last two steps, one step behind the simulation, and
+/// where the camera puts it on the screen.
+fn sprite_logic(engine: &mut Engine, game_state: &mut GameState) {
+    let sim = &game_state.sim;
+    let camera = game_state.camera;
+    let alpha = game_state.timestep.alpha();
+    let interpolate =
+        |label: &str, position: Vec2, rotation: f32| match game_state.previous_poses.get(label) {
+            Some(&(previous_position, previous_rotation)) => (
+                camera.screen_position(previous_position.lerp(position, alpha)),
+                previous_rotation + (rotation - previous_rotation) * alpha,
+            ),
+            None => (camera.screen_position(position), rotation),
+        };
+
+    for label in ["track_inner", "track_outer"] {
+        if let Some(sprite) = engine.sprites.get_mut(label) {
+            sprite.translation = camera.screen_position(Vec2::ZERO);
+            sprite.scale = camera.zoom;
+        }
+    }
+
+    // Players are drawn above the rivals.
+    let cars: Vec<(&str, CarPreset, &Vehicle, f32)> = sim
+        .players
+        .iter()
+        .map(|player| (player.label.as_str(), player.preset, &player.car, 100.0))
+        .chain(
+            sim.rivals
+                .iter()
+                .map(|rival| (rival.label.as_str(), rival.preset, &rival.car, 99.0)),
+        )
+        .collect();
+
+    engine.sprites.retain(|label, _| {
+        if label.starts_with("enemy") {
+            sim.enemies.iter().any(|enemy| &enemy.label == label)
+        } else if label.starts_with("pickup") {
+            sim.pickups.iter().any(|pickup| &pickup.label == label)
+        } else {
+            true
+        }
+    });
+
+    for &(label, preset, car, layer) in &cars {
+        let sprite = match engine.sprites.get_mut(label) {
+            Some(s) => s,
+            _ => {
+                let new_sprite = engine.add_sprite(label, preset.sprite());
+                new_sprite.layer = layer;
+                new_sprite
+            }
+        };
+
+        (sprite.translation, sprite.rotation) = interpolate(label, car.position, car.heading);
+        sprite.scale = CAR_SCALE * camera.zoom;
+    }
+
+    // Each player's ghost drives the best lap on that player's lap clock, held back as far as the
+    // cars are.
+    for player in &sim.players {
+        let label = format!("ghost_{}", player.label);
+        let pose = game_state
+            .ghost
+            .as_ref()
+            .filter(|_| game_state.phase != Phase::Menu && !player.is_done())
+            .and_then(|ghost| ghost.pose((player.lap_time - (1.0 - alpha) * TIMESTEP).max(0.0)));
+        let (position, rotation) = match pose {
+            Some(pose) => pose,
+            None => {
+                engine.sprites.remove(&label);
+                continue;
+            }
+        };
+
+        let sprite = match engine.sprites.get_mut(&label) {
+            Some(s) => s,
+            _ => {
+                let new_sprite = engine.add_sprite(label.clone(), player.preset.ghost_sprite());
+                new_sprite.layer = 98.0;
+                new_sprite
+            }
+        };
+
+        sprite.translation = camera.screen_position(position);
+        sprite.rotation = rotation;
+        sprite.scale = CAR_SCALE * camera.zoom;
+    }
+
+    for pickup in &sim.pickups {
+        let sprite = match engine.sprites.get_mut(pickup.label.as_str()) {
+            Some(s) => s,
+            _ => {
+                let new_sprite = engine.add_sprite(pickup.label.clone(), pickup.kind.sprite());
+                new_sprite.layer = 1.0;
+                new_sprite
+            }
+        };
+
+        sprite.translation = camera.screen_position(pickup.position);
+        sprite.scale = camera.zoom;
+    }
+
+    for enemy in &sim.enemies {
         let sprite = match engine.sprites.get_mut(enemy.label.as_str()) {
             Some(s) => s,
             _ => {
-                let new_sprite =
-                    engine.add_sprite(enemy.label.clone(), SpritePreset::RacingBarrelRed);
+                let new_sprite = engine.add_sprite(enemy.label.clone(), enemy.behaviour.sprite());
                 new_sprite.layer = 1.0;
-                new_sprite.collision = true;
                 new_sprite
             }
         };
 
-        sprite.translation.x =
-            enemy.position.x + enemy.direction.cos() * enemy.amplitude * time.sin() as f32;
-        sprite.translation.y =
-            enemy.position.y + enemy.direction.sin() * enemy.amplitude * time.sin() as f32;
+        (sprite.translation, _) = interpolate(&enemy.label, enemy.translation, 0.0);
+        sprite.scale = camera.zoom;
     }
 }
 
-fn collision_logic(engine: &mut Engine, game_state: &mut GameState) {
-    for collision_event in &engine.collision_events {
-        println!(
-            "Collision between: {} and {}, {}",
-            collision_event.pair.0,
-            collision_event.pair.1,
-            match collision_event.state {
-                CollisionState::Begin => "Begin",
-                _ => "End",
-            }
-        );
+fn sound_logic(engine: &mut Engine, game_state: &mut GameState) {
+    let events = game_state
+        .sim
+        .players
+        .iter()
+        .flat_map(|player| &player.events);
 
-        if collision_event.pair.one_starts_with("track_inner")
-            && collision_event.pair.one_starts_with("player")
-        {
-            match collision_event.state {
-                CollisionState::Begin => {
-                    game_state.player_hit = true;
-                    engine.audio_manager.play_sfx(SfxPreset::Impact1, 0.4);
-                }
-                _ => game_state.player_hit = false,
+    for event in events {
+        match event {
+            GameEvent::WallHit { side, .. } => {
+                // Scraping along the wall sounds different from running into it.
+                let sfx = match side {
+                    Side::Front | Side::Rear => SfxPreset::Impact1,
+                    Side::Left | Side::Right => SfxPreset::Impact2,
+                };
+                engine
+                    .audio_manager
+                    .play_sfx(sfx, game_state.settings.sfx());
+            }
+            GameEvent::CarHit { .. } => {
+                engine
+                    .audio_manager
+                    .play_sfx(SfxPreset::Impact3, game_state.settings.sfx());
+            }
+            GameEvent::LeftTrack => {
+                engine
+                    .audio_manager
+                    .play_sfx(SfxPreset::Impact1, game_state.settings.sfx());
             }
+            GameEvent::EnemyCollected { points, .. } => {
+                let sfx = if *points > 0 {
+                    SfxPreset::Confirmation1
+                } else {
+                    SfxPreset::Minimize1
+                };
+                engine
+                    .audio_manager
+                    .play_sfx(sfx, game_state.settings.sfx());
+            }
+            GameEvent::PickupCollected { .. } => {
+                engine
+                    .audio_manager
+                    .play_sfx(SfxPreset::Forcefield1, game_state.settings.sfx());
+            }
+            GameEvent::LapCompleted { .. } => {
+                engine
+                    .audio_manager
+                    .play_sfx(SfxPreset::Jingle1, game_state.settings.sfx());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Shows every score where it was made, as a text that floats up for a moment.
+fn popup_logic(engine: &mut Engine, game_state: &mut GameState) {
+    game_state.popups.retain(|(label, _, age)| {
+        let keep = *age < POPUP_SECONDS;
+        if !keep {
+            engine.texts.remove(label);
         }
+        keep
+    });
 
-        if collision_event.pair.one_starts_with("track_outer")
-            && collision_event.pair.one_starts_with("player")
+    let events = game_state
+        .sim
+        .players
+        .iter()
+        .flat_map(|player| &player.events);
+    for event in events {
+        if let GameEvent::Scored {
+            points,
+            multiplier,
+            position,
+        } = *event
         {
-            match collision_event.state {
-                CollisionState::End => {
-                    game_state.player_hit = true;
-                    engine.audio_manager.play_sfx(SfxPreset::Impact1, 0.4);
-                }
-                _ => game_state.player_hit = false,
-            }
+            let label = format!("popup_{}", game_state.next_popup_id);
+            game_state.next_popup_id += 1;
+
+            let value = match multiplier {
+                1 => format!("{:+}", points),
+                _ => format!("{:+} x{}", points, multiplier),
+            };
+            let text = engine.add_text(label.clone(), value);
+            text.font_size = 30.0;
+            game_state.popups.push((label, position, 0.0));
+        }
+    }
+
+    // Popups belong to the world, so they scroll with it.
+    for (label, position, age) in &mut game_state.popups {
+        if let Some(text) = engine.texts.get_mut(label.as_str()) {
+            let risen = *position + Vec2::new(0.0, POPUP_RISE * *age);
+            text.translation = game_state.camera.screen_position(risen);
         }
+        *age += engine.delta_f32;
     }
 }
 
-const HIT_RATE: f32 = 10.0;
+/// Every player gets their own block of text along the top of the screen, side by side, in three
+/// columns. The HUD is placed on the screen rather than in the world, so the camera leaves it be.
+fn hud_logic(engine: &mut Engine, game_state: &mut GameState) {
+    let sim = &game_state.sim;
+    let standings = sim.standings();
+    let window = engine.window_dimensions;
+    let block_width = window.x / sim.players.len() as f32;
+
+    engine.texts.retain(|label, _| {
+        !label.starts_with("player_")
+            || sim
+                .players
+                .iter()
+                .any(|player| label.starts_with(&format!("{}_", player.label)))
+    });
+
+    for (i, player) in sim.players.iter().enumerate() {
+        let position = standings
+            .iter()
+            .position(|standing| standing.label == player.label)
+            .unwrap();
 
-fn scoring_logic(engine: &mut Engine, game_state: &mut GameState) {
-    if game_state.player_hit {
-        game_state.health -= HIT_RATE * engine.delta_f32;
+        let left = -window.x / 2.0 + i as f32 * block_width;
+        let columns = [
+            left + 60.0,
+            left + block_width / 2.0,
+            left + block_width - 200.0,
+        ];
+
+        let lines = [
+            (0, 0, "health", format!("Health {}", player.health as i32)),
+            (1, 0, "score", format!("Score {}", player.score)),
+            (
+                2,
+                0,
+                "speed",
+                format!("Speed {}", player.car.speed() as i32),
+            ),
+            (
+                0,
+                1,
+                "lap",
+                match sim.laps() {
+                    Some(laps) => format!("Lap {}/{}", player.lap.min(laps), laps),
+                    None => format!("Lap {}", player.lap),
+                },
+            ),
+            (1, 1, "lap_time", format!("Time {:.2}", player.lap_time)),
+            (
+                2,
+                1,
+                "best_lap",
+                match player.best_lap {
+                    Some(best_lap) => format!("Best {:.2}", best_lap),
+                    None => "Best --".to_string(),
+                },
+            ),
+            (
+                0,
+                3,
+                "power_ups",
+                player
+                    .power_ups
+                    .remaining()
+                    .map(|(kind, remaining)| format!("{:?} {:.0}", kind, remaining.ceil()))
+                    .collect::<Vec<_>>()
+                    .join("  "),
+            ),
+            (
+                2,
+                3,
+                "combo",
+                match player.combo.multiplier() {
+                    1 => String::new(),
+                    multiplier => format!("Combo x{}", multiplier),
+                },
+            ),
+            (
+                1,
+                3,
+                "ghost",
+                match game_state.attempts.get(i).and_then(|attempt| attempt.delta) {
+                    Some(delta) => format!("Ghost {:+.2}", delta),
+                    None => String::new(),
+                },
+            ),
+            (
+                0,
+                2,
+                "position",
+                format!("Position {}/{}", ordinal(position + 1), standings.len()),
+            ),
+            (
+                1,
+                2,
+                "gap",
+                match (position, standings[position].gap) {
+                    (0, _) => "Leader".to_string(),
+                    (_, Some(gap)) => format!("Gap +{:.2}", gap),
+                    (_, None) => "Gap --".to_string(),
+                },
+            ),
+            (
+                2,
+                2,
+                "name",
+                if sim.players.len() > 1 {
+                    player.name.clone()
+                } else {
+                    String::new()
+                },
+            ),
+        ];
+
+        for (column, row, name, value) in lines {
+            let label = format!("{}_{}", player.label, name);
+            let text = match engine.texts.get_mut(&label) {
+                Some(t) => t,
+                _ => engine.add_text(label.clone(), ""),
+            };
+            text.translation = Vec2::new(
+                columns[column],
+                window.y / 2.0 - (row + 1) as f32 * (text.font_size + 5.0),
+            );
+            text.value = value;
+        }
     }
 
-    for collision_event in engine.collision_events.drain(..) {
-        if collision_event.pair.one_starts_with("enemy")
-            && collision_event.pair.one_starts_with("player")
-        {
-            match collision_event.state {
-                CollisionState::Begin => {
-                    game_state.score += 10;
-                    engine.audio_manager.play_sfx(SfxPreset::Confirmation1, 0.4);
-                }
-                _ => {}
+    let bindings = &game_state.input.bindings;
+    let kept_replay = match &game_state.kept_replay {
+        Some(path) => format!("\nSaved replay to {}", path.display()),
+        None => String::new(),
+    };
+    let restart_hint = match game_state.pending_initials.first() {
+        Some(&player) => format!(
+            "\n{}, new high score!\nEnter your initials: {}_",
+            sim.players[player].name, game_state.initials
+        ),
+        None if game_state.playback.is_some() => {
+            "\nPress Enter to watch again or Escape for the menu".to_string()
+        }
+        None => format!(
+            "\nPress Enter or {} to restart, F5 to keep the replay{}",
+            key_name(bindings, Action::Restart),
+            kept_replay
+        ),
+    };
+
+    let message_text = engine.texts.get_mut("message").unwrap();
+    message_text.value = match game_state.phase {
+        Phase::Menu => "Press 1 or Enter for one player, 2 for two players\n\
+             Press B to change the controls, O for options, L for the leaderboard\n\
+             Press P to watch the last race"
+            .to_string(),
+        Phase::Countdown => {
+            let remaining = COUNTDOWN_SECONDS - game_state.countdown.elapsed_secs();
+            format!("{}", remaining.ceil().max(1.0) as i32)
+        }
+        Phase::Racing => String::new(),
+        Phase::Paused => format!(
+            "Paused\nPress {} to continue or {} to restart, F5 to keep the replay{}",
+            key_name(bindings, Action::Pause),
+            key_name(bindings, Action::Restart),
+            kept_replay
+        ),
+        Phase::Bindings | Phase::Leaderboard | Phase::Options => String::new(),
+        Phase::ReplayOver => "End of replay".to_string() + &restart_hint,
+        Phase::GameOver => {
+            let mut message = "Game over".to_string();
+            for player in &sim.players {
+                message += &match sim.players.len() {
+                    1 => format!("\nFinal score {}", player.score),
+                    _ => format!("\n{} score {}", player.name, player.score),
+                };
             }
+            message + &restart_hint
+        }
+        Phase::Finished => {
+            let mut results = "Results".to_string();
+            for (i, standing) in standings.iter().enumerate() {
+                let time = match standing.finish_time {
+                    Some(time) => format!("{:.2}", time),
+                    None => "--".to_string(),
+                };
+                results += &format!("\n{} {} {}", ordinal(i + 1), standing.name, time);
+            }
+            results + &restart_hint
+        }
+    };
+
+    let screen_text = engine.texts.get_mut("screen").unwrap();
+    screen_text.value = match game_state.phase {
+        Phase::Bindings => bindings_screen(bindings, game_state.rebinding),
+        Phase::Leaderboard => leaderboard_screen(
+            &game_state.high_scores,
+            &sim.track.name,
+            game_state.settings.difficulty,
+        ),
+        Phase::Options => options_screen(&game_state.settings, game_state.options_row),
+        _ => String::new(),
+    };
+}
+
+/// First key of the first player bound to `action`, for hints on screen.
+fn key_name(bindings: &Bindings, action: Action) -> String {
+    bindings
+        .keys(0, action)
+        .first()
+        .map_or_else(|| "--".to_string(), |key| key.name())
+}
+
+fn bindings_screen(bindings: &Bindings, rebinding: Rebinding) -> String {
+    let mut screen = "Controls".to_string();
+    for player in 0..bindings.players.len() {
+        for (i, &action) in Action::ALL.iter().enumerate() {
+            let row = player * Action::ALL.len() + i;
+            let keys = if rebinding.capturing && row == rebinding.row {
+                "press a key or a gamepad button, Backspace to cancel".to_string()
+            } else {
+                let names: Vec<String> = bindings
+                    .keys(player, action)
+                    .iter()
+                    .map(|key| key.name())
+                    .chain(
+                        bindings
+                            .pad_inputs(player, action)
+                            .iter()
+                            .map(|input| format!("{:?}", input)),
+                    )
+                    .collect();
+                names.join(", ")
+            };
+            let marker = if row == rebinding.row { "> " } else { "" };
+            screen += &format!("\n{}Player {} {:?}: {}", marker, player + 1, action, keys);
         }
     }
+    screen + "\nUp/Down to choose, Enter to rebind, Esc to save and go back"
 }
 
-fn enemy_spawn_logic(engine: &mut Engine, game_state: &mut GameState) {
-    if game_state.spawn_timer.tick(engine.delta).just_finished() {
-        game_state.spawn_timer = Timer::from_seconds(thread_rng().gen_range(1.5..3.5), false);
-        println!("Would spawn enemy");
+fn options_screen(settings: &Settings, selected: usize) -> String {
+    let mut screen = "Options".to_string();
+    for (row, &setting) in Setting::ALL.iter().enumerate() {
+        let marker = if row == selected { "> " } else { "" };
+        let note = if setting.needs_restart() {
+            " (after a restart)"
+        } else {
+            ""
+        };
+        screen += &format!(
+            "\n{}{}: {}{}",
+            marker,
+            setting.name(),
+            settings.value(setting),
+            note
+        );
     }
+    screen + "\nUp/Down to choose, Left/Right to change, Esc to save and go back"
 }
 
-fn hud_logic(engine: &mut Engine, game_state: &mut GameState) {
-    let speed_text = engine.texts.get_mut("speed").unwrap();
-    speed_text.translation = Vec2::new(
-        engine.window_dimensions.x / 2.0 - 200.0,
-        engine.window_dimensions.y / 2.0 - speed_text.font_size - 5.0,
-    );
-    speed_text.value = format!("Speed {}", game_state.speed);
+/// Tables for the difficulty set in the options, which is the one the next run is played at.
+fn leaderboard_screen(high_scores: &HighScores, track: &str, difficulty: Difficulty) -> String {
+    let mut screen = format!("Leaderboard {} ({:?})", track, difficulty);
+    let mut empty = true;
+    for car in [
+        CarPreset::Green,
+        CarPreset::Red,
+        CarPreset::Blue,
+        CarPreset::Yellow,
+        CarPreset::Black,
+    ] {
+        let table = match high_scores.table(track, car, difficulty) {
+            Some(table) => table,
+            None => continue,
+        };
+        empty = false;
 
-    let score_text = engine.texts.get_mut("score").unwrap();
-    score_text.translation = Vec2::new(
-        0.0,
-        engine.window_dimensions.y / 2.0 - score_text.font_size - 5.0,
-    );
-    score_text.value = format!("Score {}", game_state.score);
+        screen += &format!("\n\n{:?}", car);
+        if let Some(best_lap) = &table.best_lap {
+            screen += &format!("   best lap {:.2} {}", best_lap.time, best_lap.initials);
+        }
+        for (i, entry) in table.scores.iter().enumerate() {
+            screen += &format!("\n{} {} {}", ordinal(i + 1), entry.initials, entry.score);
+        }
+    }
 
-    let health_text = engine.texts.get_mut("health").unwrap();
-    health_text.translation = Vec2::new(
-        -1.0 * engine.window_dimensions.x / 2.0 + 60.0,
-        engine.window_dimensions.y / 2.0 - health_text.font_size - 5.0,
-    );
-    health_text.value = format!("Health {}", game_state.health as i32);
+    if empty {
+        screen += "\n\nNo scores yet";
+    }
+    screen + "\n\nPress Esc to go back"
 }
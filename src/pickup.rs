@@ -0,0 +1,136 @@
+use crate::vehicle::Handling;
+use rusty_engine::prelude::*;
+use std::collections::BTreeMap;
+
+/// Health a repair kit gives back.
+pub const REPAIR_HEALTH: f32 = 30.0;
+/// Top speed and acceleration with nitro, as multiples of the car's own.
+const NITRO_TOP_SPEED: f32 = 1.3;
+const NITRO_ACCELERATION: f32 = 1.5;
+/// Points from enemies are worth this many times as much while the multiplier runs.
+const SCORE_MULTIPLIER: i32 = 2;
+
+/// Every pickup collider, relative to the `assets` directory, so they can be loaded up front.
+pub const COLLIDERS: [&str; 4] = [
+    "sprite/rolling/block_small.collider",
+    "sprite/rolling/ball_red_alt.collider",
+    "sprite/rolling/ball_blue.collider",
+    "sprite/rolling/ball_blue_alt.collider",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// Gives back `REPAIR_HEALTH` right away.
+    Repair,
+    /// Faster top speed and acceleration.
+    Nitro,
+    /// Wall impacts cost no health.
+    Shield,
+    /// Enemies are worth more points.
+    Multiplier,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 4] = [Kind::Repair, Kind::Nitro, Kind::Shield, Kind::Multiplier];
+
+    pub fn sprite(self) -> SpritePreset {
+        match self {
+            Kind::Repair => SpritePreset::RollingBlockSmall,
+            Kind::Nitro => SpritePreset::RollingBallRedAlt,
+            Kind::Shield => SpritePreset::RollingBallBlue,
+            Kind::Multiplier => SpritePreset::RollingBallBlueAlt,
+        }
+    }
+
+    /// Collider of the sprite, one of `COLLIDERS`.
+    pub fn collider(self) -> &'static str {
+        match self {
+            Kind::Repair => COLLIDERS[0],
+            Kind::Nitro => COLLIDERS[1],
+            Kind::Shield => COLLIDERS[2],
+            Kind::Multiplier => COLLIDERS[3],
+        }
+    }
+
+    /// Seconds the power-up lasts. Zero for the ones that act once.
+    pub fn duration(self) -> f32 {
+        match self {
+            Kind::Repair => 0.0,
+            Kind::Nitro => 4.0,
+            Kind::Shield => 6.0,
+            Kind::Multiplier => 8.0,
+        }
+    }
+
+    /// How often the kind turns up, relative to the others.
+    pub fn spawn_weight(self) -> u32 {
+        match self {
+            Kind::Repair => 3,
+            Kind::Nitro => 3,
+            Kind::Shield => 2,
+            Kind::Multiplier => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pickup {
+    pub label: String,
+    pub kind: Kind,
+    pub position: Vec2,
+}
+
+/// Power-ups a player has running, with the seconds left of each.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PowerUps {
+    timers: BTreeMap<Kind, f32>,
+}
+
+impl PowerUps {
+    /// Starts the power-up, or gives it its full time again if it was running already.
+    pub fn start(&mut self, kind: Kind) {
+        if kind.duration() > 0.0 {
+            self.timers.insert(kind, kind.duration());
+        }
+    }
+
+    pub fn tick(&mut self, delta: f32) {
+        for remaining in self.timers.values_mut() {
+            *remaining -= delta;
+        }
+        self.timers.retain(|_, remaining| *remaining > 0.0);
+    }
+
+    pub fn is_active(&self, kind: Kind) -> bool {
+        self.timers.contains_key(&kind)
+    }
+
+    /// Running power-ups and their seconds left, in `Kind` order.
+    pub fn remaining(&self) -> impl Iterator<Item = (Kind, f32)> + '_ {
+        self.timers
+            .iter()
+            .map(|(&kind, &remaining)| (kind, remaining))
+    }
+
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+
+    /// The car's own handling, with nitro on top if it is running.
+    pub fn handling(&self, mut handling: Handling) -> Handling {
+        if self.is_active(Kind::Nitro) {
+            handling.top_speed *= NITRO_TOP_SPEED;
+            handling.acceleration *= NITRO_ACCELERATION;
+        }
+        handling
+    }
+
+    /// What rewards are multiplied by on top of the combo.
+    pub fn score_multiplier(&self) -> i32 {
+        if self.is_active(Kind::Multiplier) {
+            SCORE_MULTIPLIER
+        } else {
+            1
+        }
+    }
+}
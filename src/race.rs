@@ -0,0 +1,175 @@
+use crate::geometry::closest_point_on_segment;
+use rusty_engine::prelude::*;
+use std::cmp::Ordering;
+
+/// Closed line through the middle of the track, used to measure how far around a lap a car is.
+#[derive(Clone, Debug, Default)]
+pub struct Centerline {
+    points: Vec<Vec2>,
+    /// Distance along the line to each point, starting from the first one.
+    distances: Vec<f32>,
+    length: f32,
+    /// Distance along the line to the finish line.
+    start: f32,
+}
+
+impl Centerline {
+    pub fn new(points: Vec<Vec2>, finish_line: Vec2) -> Self {
+        let mut distances = Vec::with_capacity(points.len());
+        let mut length = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            distances.push(length);
+            length += point.distance(points[(i + 1) % points.len()]);
+        }
+
+        let mut centerline = Self {
+            points,
+            distances,
+            length,
+            start: 0.0,
+        };
+        centerline.start = centerline.distance_along(finish_line);
+        centerline
+    }
+
+    /// Share of a lap driven from the finish line to `point`, between 0 and 1.
+    pub fn lap_fraction(&self, point: Vec2) -> f32 {
+        if self.length <= 0.0 {
+            return 0.0;
+        }
+        ((self.distance_along(point) - self.start) / self.length).rem_euclid(1.0)
+    }
+
+    /// Distance from the first point to the spot on the line closest to `point`.
+    fn distance_along(&self, point: Vec2) -> f32 {
+        let segments = self.points.iter().enumerate().map(|(i, &a)| {
+            let b = self.points[(i + 1) % self.points.len()];
+            let closest = closest_point_on_segment(point, a, b);
+            (
+                closest.distance(point),
+                self.distances[i] + a.distance(closest),
+            )
+        });
+
+        segments
+            .min_by(|(p, _), (q, _)| p.total_cmp(q))
+            .map_or(0.0, |(_, along)| along)
+    }
+}
+
+/// One car's place in the race.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub label: String,
+    pub name: String,
+    /// Laps driven, including the share of the current lap.
+    pub progress: f32,
+    /// Race time at which the car took the chequered flag.
+    pub finish_time: Option<f32>,
+    /// Seconds behind the leader at the last gate both of them drove through. `None` for the
+    /// leader, and for cars that have not reached a gate yet.
+    pub gap: Option<f32>,
+    /// Race time at every gate driven through, checkpoints and finish line alike.
+    pub splits: Vec<f32>,
+}
+
+impl Standing {
+    /// Laps driven, counting the part of the lap behind the finish line as not started yet until
+    /// the car has driven through the first checkpoint.
+    pub fn progress(lap: u32, next_checkpoint: usize, lap_fraction: f32) -> f32 {
+        let lap_fraction = if next_checkpoint == 0 && lap_fraction > 0.5 {
+            lap_fraction - 1.0
+        } else {
+            lap_fraction
+        };
+        lap.saturating_sub(1) as f32 + lap_fraction
+    }
+}
+
+/// Sorts cars into race order and fills in the gaps to the leader. Finished cars come first, in
+/// the order they finished, then everybody else by progress.
+pub fn rank(mut standings: Vec<Standing>) -> Vec<Standing> {
+    standings.sort_by(|a, b| match (a.finish_time, b.finish_time) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => b.progress.total_cmp(&a.progress),
+    });
+
+    let leader_splits = match standings.first() {
+        Some(leader) => leader.splits.clone(),
+        None => return standings,
+    };
+    for standing in standings.iter_mut().skip(1) {
+        let gate = standing.splits.len().checked_sub(1);
+        standing.gap = gate.and_then(|gate| Some(standing.splits[gate] - leader_splits.get(gate)?));
+    }
+
+    standings
+}
+
+/// Position as shown to players: 1st, 2nd, 3rd, 4th...
+pub fn ordinal(position: usize) -> String {
+    let suffix = match (position % 10, position % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", position, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing(label: &str, progress: f32, finish_time: Option<f32>, splits: &[f32]) -> Standing {
+        Standing {
+            label: label.to_string(),
+            name: label.to_string(),
+            progress,
+            finish_time,
+            gap: None,
+            splits: splits.to_vec(),
+        }
+    }
+
+    #[test]
+    fn lap_fraction_starts_at_the_finish_line() {
+        let square = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(100.0, 0.0),
+            Vec2::new(100.0, 100.0),
+            Vec2::new(0.0, 100.0),
+        ];
+        let centerline = Centerline::new(square, Vec2::new(50.0, -10.0));
+
+        assert!(centerline.lap_fraction(Vec2::new(50.0, 5.0)).abs() < 1e-5);
+        assert!((centerline.lap_fraction(Vec2::new(50.0, 110.0)) - 0.5).abs() < 1e-5);
+        assert!((centerline.lap_fraction(Vec2::new(0.0, 50.0)) - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn behind_the_line_before_the_first_checkpoint_is_not_ahead() {
+        assert!((Standing::progress(1, 0, 0.9) + 0.1).abs() < 1e-5);
+        assert!((Standing::progress(2, 2, 0.9) - 1.9).abs() < 1e-5);
+    }
+
+    #[test]
+    fn finished_cars_lead_and_gaps_use_the_same_gate() {
+        let standings = rank(vec![
+            standing("slow", 2.5, None, &[1.0, 2.5, 4.0]),
+            standing("second", 3.0, Some(11.0), &[1.0, 2.0, 3.5, 11.0]),
+            standing("winner", 3.0, Some(10.0), &[0.5, 1.5, 3.0, 10.0]),
+            standing("stalled", 0.1, None, &[]),
+        ]);
+
+        let labels: Vec<&str> = standings.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, ["winner", "second", "slow", "stalled"]);
+        assert_eq!(standings[0].gap, None);
+        assert_eq!(standings[1].gap, Some(1.0));
+        assert_eq!(standings[2].gap, Some(1.0));
+        assert_eq!(standings[3].gap, None);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::difficulty::Difficulty;
+use crate::storage;
+use crate::vehicle::{CarPreset, Controls};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Every run is written here when it ends, in the user data directory.
+const LATEST_REPLAY: &str = "replays/latest.ron";
+
+/// Everything needed to run a race again exactly: the setup, the seed of the random number
+/// generator and the input of every simulation step. Controls are recorded after the bindings
+/// are applied, so a replay does not depend on anybody's key layout.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Replay {
+    pub track: String,
+    pub players: Vec<CarPreset>,
+    pub seed: u64,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub frames: Vec<Frame>,
+}
+
+/// One or more identical steps in a row.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Frame {
+    pub delta: f32,
+    /// One entry per player.
+    pub controls: Vec<Controls>,
+    #[serde(default = "one")]
+    pub repeat: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl Replay {
+    pub fn new(track: &str, players: Vec<CarPreset>, seed: u64, difficulty: Difficulty) -> Self {
+        Self {
+            track: track.to_string(),
+            players,
+            seed,
+            difficulty,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn latest_path() -> PathBuf {
+        storage::user_file(LATEST_REPLAY)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read replay {}: {}", path.display(), e))?;
+        ron::from_str(&contents)
+            .map_err(|e| format!("Could not parse replay {}: {}", path.display(), e))
+    }
+
+    /// Writes the replay on a single line; replays get long and nobody edits them by hand.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string(self)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        storage::write(path, &contents)
+    }
+
+    /// Adds a frame, folding it into the previous one when nothing changed.
+    pub fn record(&mut self, delta: f32, controls: &[Controls]) {
+        match self.frames.last_mut() {
+            Some(last) if last.delta == delta && last.controls == controls => last.repeat += 1,
+            _ => self.frames.push(Frame {
+                delta,
+                controls: controls.to_vec(),
+                repeat: 1,
+            }),
+        }
+    }
+}
+
+/// Feeds a replay back step by step.
+#[derive(Clone, Debug, Default)]
+pub struct Playback {
+    pub replay: Replay,
+    frame: usize,
+    repeat: u32,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            frame: 0,
+            repeat: 0,
+        }
+    }
+
+    pub fn rewind(&mut self) {
+        self.frame = 0;
+        self.repeat = 0;
+    }
+
+    /// Delta time and controls of the next frame, or `None` once the replay is over.
+    pub fn next_frame(&mut self) -> Option<(f32, &[Controls])> {
+        let frame = self.replay.frames.get(self.frame)?;
+        self.repeat += 1;
+        if self.repeat >= frame.repeat {
+            self.frame += 1;
+            self.repeat = 0;
+        }
+        Some((frame.delta, &frame.controls))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Simulation;
+    use crate::track::{Track, DEFAULT_TRACK};
+    use rand::prelude::*;
+
+    #[test]
+    fn playback_repeats_the_run_exactly() {
+        let track = Track::load(DEFAULT_TRACK).unwrap();
+        let players = vec![CarPreset::Green, CarPreset::Red];
+        let mut replay = Replay::new(&track.name, players.clone(), 42, Difficulty::Normal);
+
+        // Wobbly input and frame times, like a real session.
+        let mut recorded = Simulation::new(track.clone(), &players).unwrap();
+        let mut rng = StdRng::seed_from_u64(replay.seed);
+        let mut input = StdRng::seed_from_u64(1);
+        for _ in 0..60 * 10 {
+            let delta = input.gen_range(0.012..0.02);
+            let controls: Vec<Controls> = (0..players.len())
+                .map(|_| Controls {
+                    throttle: 1.0,
+                    brake: 0.0,
+                    steering: input.gen_range(-1.0..1.0),
+                })
+                .collect();
+            replay.record(delta, &controls);
+            recorded.step(&controls, delta, &mut rng);
+        }
+
+        let replay: Replay = ron::from_str(&ron::ser::to_string(&replay).unwrap()).unwrap();
+        let mut played = Simulation::new(track, &replay.players).unwrap();
+        let mut rng = StdRng::seed_from_u64(replay.seed);
+        let mut playback = Playback::new(replay);
+        while let Some((delta, controls)) = playback.next_frame() {
+            played.step(controls, delta, &mut rng);
+        }
+
+        assert_eq!(format!("{:?}", played), format!("{:?}", recorded));
+    }
+
+    #[test]
+    fn identical_frames_are_folded() {
+        let mut replay = Replay::new("track01", vec![CarPreset::Green], 0, Difficulty::Normal);
+        let idle = [Controls::default()];
+        for _ in 0..3 {
+            replay.record(0.5, &idle);
+        }
+        replay.record(0.25, &idle);
+
+        assert_eq!(replay.frames.len(), 2);
+        assert_eq!(replay.frames[0].repeat, 3);
+
+        let mut playback = Playback::new(replay);
+        let deltas: Vec<f32> =
+            std::iter::from_fn(|| playback.next_frame().map(|(d, _)| d)).collect();
+        assert_eq!(deltas, [0.5, 0.5, 0.5, 0.25]);
+    }
+}
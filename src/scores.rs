@@ -0,0 +1,252 @@
+use crate::difficulty::Difficulty;
+use crate::storage;
+use crate::vehicle::CarPreset;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Kept in the user data directory, next to the bindings.
+const SCORES_FILE: &str = "high_scores.ron";
+/// Scores kept per track, car and difficulty.
+pub const MAX_ENTRIES: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Entry {
+    pub initials: String,
+    pub score: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LapRecord {
+    pub initials: String,
+    pub time: f32,
+}
+
+/// Best results on one track in one car at one difficulty.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Table {
+    /// Highest score first.
+    pub scores: Vec<Entry>,
+    pub best_lap: Option<LapRecord>,
+}
+
+impl Table {
+    fn makes_scores(&self, score: i32) -> bool {
+        score > 0
+            && (self.scores.len() < MAX_ENTRIES
+                || self.scores.last().is_some_and(|last| score > last.score))
+    }
+
+    fn beats_lap(&self, time: Option<f32>) -> bool {
+        time.is_some_and(|time| self.best_lap.as_ref().is_none_or(|best| time < best.time))
+    }
+}
+
+/// Every table, keyed by track name, car and difficulty, so each difficulty has a leaderboard of
+/// its own.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct HighScores {
+    tables: BTreeMap<(String, CarPreset, Difficulty), Table>,
+}
+
+/// Scores files from before difficulties existed, when every run was set at `Normal`.
+#[derive(Deserialize)]
+struct UnrankedHighScores {
+    tables: BTreeMap<(String, CarPreset), Table>,
+}
+
+impl From<UnrankedHighScores> for HighScores {
+    fn from(scores: UnrankedHighScores) -> Self {
+        let tables = scores
+            .tables
+            .into_iter()
+            .map(|((track, car), table)| ((track, car, Difficulty::Normal), table))
+            .collect();
+        Self { tables }
+    }
+}
+
+impl HighScores {
+    /// Reads the scores file. A missing or broken file gives an empty table, since losing the
+    /// scores is better than not being able to play.
+    pub fn load() -> Self {
+        Self::load_from(&storage::user_file(SCORES_FILE))
+    }
+
+    fn load_from(path: &Path) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default(),
+        };
+
+        ron::from_str(&contents)
+            .or_else(|e| {
+                ron::from_str::<UnrankedHighScores>(&contents)
+                    .map(HighScores::from)
+                    .map_err(|_| e)
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Could not parse high scores {}: {}", path.display(), e);
+                Self::default()
+            })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save(&storage::user_file(SCORES_FILE), self)
+    }
+
+    pub fn table(&self, track: &str, car: CarPreset, difficulty: Difficulty) -> Option<&Table> {
+        self.tables.get(&(track.to_string(), car, difficulty))
+    }
+
+    /// Whether a run would make it onto the table, by score or by lap time.
+    pub fn qualifies(
+        &self,
+        track: &str,
+        car: CarPreset,
+        score: i32,
+        best_lap: Option<f32>,
+        difficulty: Difficulty,
+    ) -> bool {
+        match self.table(track, car, difficulty) {
+            Some(table) => table.makes_scores(score) || table.beats_lap(best_lap),
+            None => score > 0 || best_lap.is_some(),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        track: &str,
+        car: CarPreset,
+        initials: &str,
+        score: i32,
+        best_lap: Option<f32>,
+        difficulty: Difficulty,
+    ) {
+        let table = self
+            .tables
+            .entry((track.to_string(), car, difficulty))
+            .or_default();
+
+        if table.makes_scores(score) {
+            // After every equal score, so older entries keep their place.
+            let index = table
+                .scores
+                .iter()
+                .position(|entry| entry.score < score)
+                .unwrap_or(table.scores.len());
+            table.scores.insert(
+                index,
+                Entry {
+                    initials: initials.to_string(),
+                    score,
+                },
+            );
+            table.scores.truncate(MAX_ENTRIES);
+        }
+
+        if let Some(time) = best_lap.filter(|_| table.beats_lap(best_lap)) {
+            table.best_lap = Some(LapRecord {
+                initials: initials.to_string(),
+                time,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_highest_scores_per_track_car_and_difficulty() {
+        let mut scores = HighScores::default();
+        for score in 1..=MAX_ENTRIES as i32 + 2 {
+            scores.record(
+                "track01",
+                CarPreset::Green,
+                "ABC",
+                score * 10,
+                None,
+                Difficulty::Normal,
+            );
+        }
+        scores.record(
+            "track01",
+            CarPreset::Red,
+            "XYZ",
+            5,
+            Some(9.5),
+            Difficulty::Hard,
+        );
+        scores.record(
+            "track01",
+            CarPreset::Red,
+            "SLO",
+            0,
+            Some(12.0),
+            Difficulty::Hard,
+        );
+        scores.record(
+            "track01",
+            CarPreset::Red,
+            "EZY",
+            500,
+            Some(8.0),
+            Difficulty::Easy,
+        );
+
+        let green = scores
+            .table("track01", CarPreset::Green, Difficulty::Normal)
+            .unwrap();
+        assert_eq!(green.scores.len(), MAX_ENTRIES);
+        assert_eq!(green.scores[0].score, 120);
+        assert!(!scores.qualifies("track01", CarPreset::Green, 20, None, Difficulty::Normal));
+        assert!(scores.qualifies("track01", CarPreset::Green, 40, None, Difficulty::Normal));
+        assert!(scores.qualifies("track01", CarPreset::Green, 20, None, Difficulty::Hard));
+
+        let red = scores
+            .table("track01", CarPreset::Red, Difficulty::Hard)
+            .unwrap();
+        assert_eq!(red.scores.len(), 1);
+        assert_eq!(red.best_lap.as_ref().unwrap().initials, "XYZ");
+        let easy = scores
+            .table("track01", CarPreset::Red, Difficulty::Easy)
+            .unwrap();
+        assert_eq!(easy.scores[0].initials, "EZY");
+        assert!(scores
+            .table("track02", CarPreset::Red, Difficulty::Hard)
+            .is_none());
+
+        let text = ron::ser::to_string(&scores).unwrap();
+        assert_eq!(ron::from_str::<HighScores>(&text).unwrap(), scores);
+    }
+
+    #[test]
+    fn scores_from_before_difficulties_load_as_normal() {
+        let path = std::env::temp_dir().join("boring_game_unranked_scores.ron");
+        fs::write(
+            &path,
+            "(tables: {(\"track01\", Green): (scores: [(initials: \"OLD\", score: 30)])})",
+        )
+        .unwrap();
+
+        let scores = HighScores::load_from(&path);
+        fs::remove_file(&path).unwrap();
+        let table = scores
+            .table("track01", CarPreset::Green, Difficulty::Normal)
+            .unwrap();
+        assert_eq!(table.scores[0].initials, "OLD");
+    }
+
+    #[test]
+    fn broken_or_missing_file_gives_an_empty_table() {
+        let path = std::env::temp_dir().join("boring_game_broken_scores.ron");
+        fs::write(&path, "(tables: {(\"track01\", Green): (scores: [oops").unwrap();
+
+        assert_eq!(HighScores::load_from(&path), HighScores::default());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(HighScores::load_from(&path), HighScores::default());
+    }
+}
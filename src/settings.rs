@@ -0,0 +1,251 @@
+use crate::difficulty::Difficulty;
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Written by the options menu, in the user data directory.
+const SETTINGS_FILE: &str = "settings.ron";
+
+/// Window sizes the options menu offers. Any size within `MIN_RESOLUTION` and `MAX_RESOLUTION`
+/// works when written into the file by hand.
+const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+const MIN_RESOLUTION: (u32, u32) = (640, 480);
+const MAX_RESOLUTION: (u32, u32) = (7680, 4320);
+/// Volumes change in steps of this much in the options menu.
+const VOLUME_STEP: f32 = 0.1;
+
+/// One line of the options menu.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Resolution,
+    Fullscreen,
+    Vsync,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Difficulty,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 7] = [
+        Setting::Resolution,
+        Setting::Fullscreen,
+        Setting::Vsync,
+        Setting::MasterVolume,
+        Setting::MusicVolume,
+        Setting::SfxVolume,
+        Setting::Difficulty,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Setting::Resolution => "Resolution",
+            Setting::Fullscreen => "Fullscreen",
+            Setting::Vsync => "VSync",
+            Setting::MasterVolume => "Master volume",
+            Setting::MusicVolume => "Music volume",
+            Setting::SfxVolume => "Sound effects volume",
+            Setting::Difficulty => "Difficulty",
+        }
+    }
+
+    /// Whether a change only shows after restarting the game. The window is set up once.
+    pub fn needs_restart(self) -> bool {
+        matches!(
+            self,
+            Setting::Resolution | Setting::Fullscreen | Setting::Vsync
+        )
+    }
+}
+
+/// Everything the player can set up outside the controls. Missing fields get their default, so
+/// older files keep working.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub resolution: (u32, u32),
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Volumes are between 0 and 1. The master volume scales both of the others.
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub difficulty: Difficulty,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            resolution: (1920, 1080),
+            fullscreen: false,
+            vsync: true,
+            master_volume: 1.0,
+            music_volume: 0.1,
+            sfx_volume: 0.4,
+            difficulty: Difficulty::Normal,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads `SETTINGS_FILE`, or gives the defaults if there is none yet.
+    pub fn load() -> Result<Self, String> {
+        let path = storage::user_file(SETTINGS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read settings {}: {}", path.display(), e))?;
+        Self::parse(&contents, &path)
+    }
+
+    fn parse(contents: &str, path: &Path) -> Result<Self, String> {
+        let settings: Self = ron::from_str(contents)
+            .map_err(|e| format!("Could not parse settings {}: {}", path.display(), e))?;
+        settings
+            .validate()
+            .map_err(|e| format!("Invalid settings {}: {}", path.display(), e))?;
+        Ok(settings)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save(&storage::user_file(SETTINGS_FILE), self)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (width, height) = self.resolution;
+        if width < MIN_RESOLUTION.0
+            || height < MIN_RESOLUTION.1
+            || width > MAX_RESOLUTION.0
+            || height > MAX_RESOLUTION.1
+        {
+            return Err(format!(
+                "resolution {}x{} is outside {}x{} to {}x{}",
+                width,
+                height,
+                MIN_RESOLUTION.0,
+                MIN_RESOLUTION.1,
+                MAX_RESOLUTION.0,
+                MAX_RESOLUTION.1
+            ));
+        }
+
+        for (name, volume) in [
+            ("master_volume", self.master_volume),
+            ("music_volume", self.music_volume),
+            ("sfx_volume", self.sfx_volume),
+        ] {
+            // Written this way round so NaN is rejected too.
+            if !(0.0..=1.0).contains(&volume) {
+                return Err(format!("{} {} is not between 0 and 1", name, volume));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn music(&self) -> f32 {
+        self.master_volume * self.music_volume
+    }
+
+    pub fn sfx(&self) -> f32 {
+        self.master_volume * self.sfx_volume
+    }
+
+    /// Steps `setting` to its next (`step` 1) or previous (`step` -1) value.
+    pub fn change(&mut self, setting: Setting, step: i32) {
+        let volume = |volume: f32| {
+            let steps = (volume / VOLUME_STEP).round() + step as f32;
+            (steps * VOLUME_STEP).clamp(0.0, 1.0)
+        };
+
+        match setting {
+            Setting::Resolution => self.resolution = cycle(&RESOLUTIONS, self.resolution, step),
+            Setting::Fullscreen => self.fullscreen = !self.fullscreen,
+            Setting::Vsync => self.vsync = !self.vsync,
+            Setting::MasterVolume => self.master_volume = volume(self.master_volume),
+            Setting::MusicVolume => self.music_volume = volume(self.music_volume),
+            Setting::SfxVolume => self.sfx_volume = volume(self.sfx_volume),
+            Setting::Difficulty => self.difficulty = cycle(&Difficulty::ALL, self.difficulty, step),
+        }
+    }
+
+    /// `setting` as the options menu shows it.
+    pub fn value(&self, setting: Setting) -> String {
+        let on_off = |on| if on { "On" } else { "Off" }.to_string();
+        let percent = |volume: f32| format!("{}%", (volume * 100.0).round());
+
+        match setting {
+            Setting::Resolution => format!("{}x{}", self.resolution.0, self.resolution.1),
+            Setting::Fullscreen => on_off(self.fullscreen),
+            Setting::Vsync => on_off(self.vsync),
+            Setting::MasterVolume => percent(self.master_volume),
+            Setting::MusicVolume => percent(self.music_volume),
+            Setting::SfxVolume => percent(self.sfx_volume),
+            Setting::Difficulty => format!("{:?}", self.difficulty),
+        }
+    }
+}
+
+/// The entry `step` places after `current` in `values`, wrapping around. A value that is not in
+/// the list, like a resolution typed into the file, moves to the first entry.
+fn cycle<T: Copy + PartialEq>(values: &[T], current: T, step: i32) -> T {
+    let len = values.len() as i32;
+    match values.iter().position(|&value| value == current) {
+        Some(index) => values[(index as i32 + step).rem_euclid(len) as usize],
+        None => values[0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_file_is_checked() {
+        let path = Path::new("settings.ron");
+        let parse = |text: &str| Settings::parse(text, path);
+
+        assert_eq!(parse("()").unwrap(), Settings::default());
+        assert!(
+            parse("(difficulty: Hard, fullscreen: true)")
+                .unwrap()
+                .fullscreen
+        );
+
+        let error = parse("(music_volume: 3.0)").unwrap_err();
+        assert!(
+            error.contains("music_volume 3 is not between 0 and 1"),
+            "{}",
+            error
+        );
+        assert!(parse("(resolution: (100, 100))").is_err());
+        assert!(parse("(difficulty: Nightmare)").is_err());
+    }
+
+    #[test]
+    fn options_step_through_their_values() {
+        let mut settings = Settings::default();
+
+        settings.change(Setting::Resolution, 1);
+        assert_eq!(settings.resolution, (2560, 1440));
+        settings.change(Setting::Difficulty, -1);
+        assert_eq!(settings.difficulty, Difficulty::Easy);
+        settings.change(Setting::Difficulty, -1);
+        assert_eq!(settings.difficulty, Difficulty::Endless);
+
+        settings.change(Setting::MusicVolume, -1);
+        settings.change(Setting::MusicVolume, -1);
+        assert_eq!(settings.music_volume, 0.0);
+        assert_eq!(settings.value(Setting::SfxVolume), "40%");
+        assert!(settings.validate().is_ok());
+    }
+}
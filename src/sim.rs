@@ -0,0 +1,1414 @@
+use crate::ai::{Driver, Skill, RIVALS};
+use crate::combo::Combo;
+use crate::difficulty::{Difficulty, Tuning};
+use crate::enemy::{self, Behaviour, Enemy};
+use crate::events::GameEvent;
+use crate::geometry::{Contact, Polygon};
+use crate::pickup::{self, Kind, Pickup, PowerUps};
+use crate::race::{self, Centerline, Standing};
+use crate::track::{Gate, SpawnZone, StartPose, Track};
+use crate::vehicle::{CarPreset, Controls, Vehicle};
+use rand::prelude::*;
+use rusty_engine::prelude::*;
+use std::collections::BTreeMap;
+
+/// Car sprites are drawn at half size, and their colliders are scaled the same way.
+pub const CAR_SCALE: f32 = 0.5;
+pub const MAX_HEALTH: f32 = 100.0;
+/// Laps to the chequered flag.
+const RACE_LAPS: u32 = 3;
+
+const WALL_IMPACT_DAMAGE: f32 = 0.05;
+const CAR_IMPACT_DAMAGE: f32 = 0.05;
+const OFF_TRACK_RATE: f32 = 5.0;
+
+/// Impacts slower than this only push the car out, so resting against the wall is free.
+const MIN_IMPACT_SPEED: f32 = 30.0;
+/// Share of the speed into the wall that comes back out as a bounce.
+const WALL_RESTITUTION: f32 = 0.3;
+/// Share of the speed along the wall that is kept after a hard impact.
+const WALL_FRICTION: f32 = 0.7;
+/// Share of the closing speed that two cars bounce apart with.
+const CAR_RESTITUTION: f32 = 0.5;
+
+/// Points for a lap without touching the wall.
+const CLEAN_LAP_BONUS: i32 = 50;
+
+const MAX_PICKUPS: usize = 2;
+/// Cones never leave the track, so they have a cap of their own outside the enemy cap.
+const MAX_CONES: usize = 4;
+/// Seconds into a run before the first pickup turns up.
+const FIRST_PICKUP_DELAY: f32 = 5.0;
+const MIN_SPAWN_DISTANCE: f32 = 150.0;
+const SPAWN_ATTEMPTS: usize = 10;
+
+/// Car driven by someone at the keyboard, and everything that belongs to their run.
+#[derive(Clone, Debug, Default)]
+pub struct Player {
+    pub label: String,
+    pub name: String,
+    pub preset: CarPreset,
+    pub car: Vehicle,
+    shape: Polygon,
+    contacts: Contacts,
+    pub health: f32,
+    pub score: i32,
+    pub lap: u32,
+    pub lap_time: f32,
+    pub best_lap: Option<f32>,
+    pub next_checkpoint: usize,
+    /// Race time at every gate driven through, checkpoints and finish line alike.
+    pub splits: Vec<f32>,
+    /// Race time at which the player took the chequered flag.
+    pub finish_time: Option<f32>,
+    pub off_track: bool,
+    pub power_ups: PowerUps,
+    pub combo: Combo,
+    /// No wall contact so far this lap.
+    pub clean_lap: bool,
+    /// Events of the most recent step that happened to this player.
+    pub events: Vec<GameEvent>,
+}
+
+impl Player {
+    /// Finished or wrecked. The car ignores its controls from then on.
+    pub fn is_done(&self) -> bool {
+        self.finish_time.is_some() || self.health <= 0.0
+    }
+
+    pub fn polygon(&self) -> Polygon {
+        self.shape
+            .transformed(self.car.position, self.car.heading, CAR_SCALE)
+    }
+}
+
+/// Computer controlled opponent.
+#[derive(Clone, Debug)]
+pub struct Rival {
+    pub label: String,
+    pub preset: CarPreset,
+    pub skill: Skill,
+    pub car: Vehicle,
+    pub driver: Driver,
+    pub lap: u32,
+    pub next_checkpoint: usize,
+    pub splits: Vec<f32>,
+    pub finish_time: Option<f32>,
+    shape: Polygon,
+}
+
+/// How quickly the car loses health.
+#[derive(Clone, Copy, Debug)]
+pub struct DamageRates {
+    /// Health lost per pixel per second of speed into the wall when hitting it.
+    pub wall_impact: f32,
+    /// Health lost per pixel per second of closing speed when two cars collide.
+    pub car_impact: f32,
+    /// Health lost per second while off the track.
+    pub off_track: f32,
+}
+
+impl Default for DamageRates {
+    fn default() -> Self {
+        Self {
+            wall_impact: WALL_IMPACT_DAMAGE,
+            car_impact: CAR_IMPACT_DAMAGE,
+            off_track: OFF_TRACK_RATE,
+        }
+    }
+}
+
+impl DamageRates {
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            wall_impact: self.wall_impact * scale,
+            car_impact: self.car_impact * scale,
+            off_track: self.off_track * scale,
+        }
+    }
+}
+
+/// Collision shapes, loaded once per track.
+#[derive(Clone, Debug, Default)]
+struct Shapes {
+    inner: Polygon,
+    outer: Polygon,
+    finish_line: Polygon,
+    checkpoints: Vec<Polygon>,
+    /// Enemy and pickup colliders by path.
+    props: BTreeMap<&'static str, Polygon>,
+    centerline: Centerline,
+}
+
+impl Shapes {
+    fn enemy(&self, enemy: &Enemy) -> Polygon {
+        self.props[enemy.behaviour.collider()].transformed(enemy.translation, 0.0, 1.0)
+    }
+
+    fn pickup(&self, pickup: &Pickup) -> Polygon {
+        self.props[pickup.kind.collider()].transformed(pickup.position, 0.0, 1.0)
+    }
+}
+
+/// What a car overlapped after the previous step, so overlaps can be turned into begin and end
+/// events like the engine does for sprites.
+#[derive(Clone, Debug, Default)]
+struct Contacts {
+    track: bool,
+    finish_line: bool,
+    checkpoints: Vec<bool>,
+}
+
+/// The gameplay rules without any rendering, audio or input. The game feeds it controls once per
+/// frame, and tests can drive it with scripted controls and a fixed delta.
+#[derive(Clone, Debug, Default)]
+pub struct Simulation {
+    pub track: Track,
+    shapes: Shapes,
+    pub players: Vec<Player>,
+    pub rivals: Vec<Rival>,
+    pub enemies: Vec<Enemy>,
+    next_enemy_id: u32,
+    pub pickups: Vec<Pickup>,
+    next_pickup_id: u32,
+    /// Rates at `Normal` difficulty. The difficulty scales them while the race runs.
+    pub damage_rates: DamageRates,
+    pub difficulty: Difficulty,
+    spawn_timer: f32,
+    pickup_timer: f32,
+    /// Seconds since the start of the run.
+    pub time: f32,
+}
+
+impl Simulation {
+    /// Sets up a race with one player per preset. Rivals fill the remaining grid spots, in cars
+    /// the players did not pick.
+    pub fn new(track: Track, player_presets: &[CarPreset]) -> Result<Self, String> {
+        // Rivals only take the poses left over, so only the players can run short.
+        let poses = track.grid.len() + 1;
+        if player_presets.len() > poses {
+            return Err(format!(
+                "Track {} has {} start poses, not enough for {} players",
+                track.name,
+                poses,
+                player_presets.len()
+            ));
+        }
+
+        let shapes = Shapes {
+            inner: Polygon::load(&track.inner.collider)?,
+            outer: Polygon::load(&track.outer.collider)?,
+            finish_line: track.finish_line.polygon(),
+            checkpoints: track.checkpoints.iter().map(Gate::polygon).collect(),
+            props: enemy::COLLIDERS
+                .iter()
+                .chain(&pickup::COLLIDERS)
+                .map(|&collider| Ok((collider, Polygon::load(collider)?)))
+                .collect::<Result<_, String>>()?,
+            centerline: Centerline::new(track.racing_line.clone(), track.finish_line.position),
+        };
+
+        let players = player_presets
+            .iter()
+            .enumerate()
+            .map(|(i, &preset)| {
+                Ok(Player {
+                    label: format!("player_{}", i + 1),
+                    name: if player_presets.len() == 1 {
+                        "You".to_string()
+                    } else {
+                        format!("Player {}", i + 1)
+                    },
+                    preset,
+                    shape: Polygon::load(preset.collider())?,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let rival_count = poses - players.len();
+        let rivals = RIVALS
+            .iter()
+            .filter(|(preset, _)| !player_presets.contains(preset))
+            .take(rival_count)
+            .enumerate()
+            .map(|(i, &(preset, skill))| {
+                Ok(Rival {
+                    label: format!("rival_{}", i + 1),
+                    preset,
+                    skill,
+                    car: Vehicle::default(),
+                    driver: Driver::new(skill),
+                    lap: 1,
+                    next_checkpoint: 0,
+                    splits: Vec::new(),
+                    finish_time: None,
+                    shape: Polygon::load(preset.collider())?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        let mut simulation = Self {
+            track,
+            shapes,
+            players,
+            rivals,
+            ..Default::default()
+        };
+        simulation.restart();
+
+        Ok(simulation)
+    }
+
+    /// Puts everything back to the start of a run. Best laps are kept, they belong to the
+    /// session rather than to a single run.
+    pub fn restart(&mut self) {
+        // The first player starts on the track's start pose, everybody else on the grid behind.
+        let poses: Vec<StartPose> = std::iter::once(self.track.start)
+            .chain(self.track.grid.iter().copied())
+            .collect();
+
+        for (player, pose) in self.players.iter_mut().zip(&poses) {
+            player.car = Vehicle::new(player.preset, pose.position, pose.rotation);
+            player.health = MAX_HEALTH;
+            player.score = 0;
+            player.lap = 1;
+            player.lap_time = 0.0;
+            player.next_checkpoint = 0;
+            player.splits.clear();
+            player.finish_time = None;
+            player.off_track = false;
+            player.power_ups.clear();
+            player.combo.reset();
+            player.clean_lap = true;
+            player.events.clear();
+        }
+
+        let rival_poses = poses.get(self.players.len()..).unwrap_or(&[]);
+        for (rival, pose) in self.rivals.iter_mut().zip(rival_poses) {
+            rival.car = Vehicle::new(rival.preset, pose.position, pose.rotation);
+            rival.driver = Driver::new(rival.skill);
+            rival.lap = 1;
+            rival.next_checkpoint = 0;
+            rival.splits.clear();
+            rival.finish_time = None;
+        }
+
+        self.enemies = self
+            .track
+            .enemies
+            .iter()
+            .enumerate()
+            .map(|(i, placement)| {
+                Enemy::new(
+                    format!("enemy_{}", i + 1),
+                    placement.behaviour.clone(),
+                    placement.position,
+                )
+            })
+            .collect();
+        self.next_enemy_id = self.enemies.len() as u32 + 1;
+
+        self.pickups.clear();
+        self.next_pickup_id = 1;
+
+        self.spawn_timer = 0.0;
+        self.pickup_timer = FIRST_PICKUP_DELAY;
+        self.time = 0.0;
+
+        for i in 0..self.players.len() {
+            self.players[i].contacts = self.current_contacts(&self.players[i]);
+        }
+    }
+
+    /// Advances the race by `delta` seconds. `controls` holds one entry per player; players
+    /// without an entry let go of everything.
+    pub fn step(&mut self, controls: &[Controls], delta: f32, rng: &mut impl Rng) {
+        self.time += delta;
+
+        for (i, player) in self.players.iter_mut().enumerate() {
+            player.events.clear();
+            player.power_ups.tick(delta);
+            player.car.handling = player.power_ups.handling(player.preset.handling());
+
+            let controls = match controls.get(i) {
+                Some(controls) if !player.is_done() => *controls,
+                _ => Controls::default(),
+            };
+            player.car.update(&controls, delta);
+        }
+
+        self.resolve_wall_contacts();
+        self.drive_rivals(delta, rng);
+        self.resolve_car_contacts();
+        self.move_enemies(delta);
+        self.push_enemies();
+        self.detect_collisions();
+        self.update_laps(delta);
+        self.update_rival_laps();
+        self.use_pickups();
+        self.update_score(delta);
+        self.update_health(delta);
+        self.spawn_enemies(delta, rng);
+        self.spawn_pickups(delta, rng);
+    }
+
+    /// How the race plays right now. Endless runs get harder as `time` goes up.
+    pub fn tuning(&self) -> Tuning {
+        self.difficulty.tuning(self.time)
+    }
+
+    /// Laps to the finish, or `None` for an Endless run, which goes on until the car is wrecked.
+    pub fn laps(&self) -> Option<u32> {
+        match self.difficulty {
+            Difficulty::Endless => None,
+            _ => Some(RACE_LAPS),
+        }
+    }
+
+    /// Whether every player is out of the race, finished or wrecked.
+    pub fn is_over(&self) -> bool {
+        self.players.iter().all(Player::is_done)
+    }
+
+    fn current_contacts(&self, player: &Player) -> Contacts {
+        let car = player.polygon();
+        Contacts {
+            track: car.overlaps(&self.shapes.outer),
+            finish_line: car.overlaps(&self.shapes.finish_line),
+            checkpoints: self
+                .shapes
+                .checkpoints
+                .iter()
+                .map(|checkpoint| car.overlaps(checkpoint))
+                .collect(),
+        }
+    }
+
+    /// Chasers go after the closest player still racing.
+    fn move_enemies(&mut self, delta: f32) {
+        let players = &self.players;
+        for enemy in &mut self.enemies {
+            let target = players
+                .iter()
+                .filter(|player| !player.is_done())
+                .map(|player| player.car.position)
+                .min_by(|a, b| {
+                    a.distance_squared(enemy.translation)
+                        .total_cmp(&b.distance_squared(enemy.translation))
+                });
+            enemy.update(self.time, delta, target);
+        }
+    }
+
+    /// Every car, players and rivals alike, shoves the enemies that cannot be collected out of
+    /// its way, and the wall keeps them on the track.
+    fn push_enemies(&mut self) {
+        let cars: Vec<(Polygon, Vec2)> = self
+            .players
+            .iter()
+            .map(|player| (player.polygon(), player.car.velocity))
+            .chain(self.rivals.iter().map(|rival| {
+                (
+                    rival
+                        .shape
+                        .transformed(rival.car.position, rival.car.heading, CAR_SCALE),
+                    rival.car.velocity,
+                )
+            }))
+            .collect();
+
+        for enemy in &mut self.enemies {
+            if enemy.behaviour.is_collectable() {
+                continue;
+            }
+            for (car, velocity) in &cars {
+                if let Some(contact) = self.shapes.enemy(enemy).contact(car) {
+                    enemy.push(contact, *velocity);
+                }
+            }
+
+            // To a sliding enemy, the wall is just a car standing still.
+            if let Some(contact) = self.shapes.enemy(enemy).contact(&self.shapes.inner) {
+                enemy.push(contact, Vec2::ZERO);
+            }
+            // The track is the inside of the outer shape, so a cone that slides past its edge is
+            // put back on it.
+            let outside = self.shapes.outer.distance(enemy.translation);
+            if outside > 0.0 {
+                let edge = self.shapes.outer.closest_point(enemy.translation);
+                let contact = Contact {
+                    normal: (edge - enemy.translation).normalize_or_zero(),
+                    depth: outside,
+                };
+                enemy.push(contact, Vec2::ZERO);
+            }
+        }
+    }
+
+    fn resolve_wall_contacts(&mut self) {
+        for player in &mut self.players {
+            if let Some((contact, impact)) =
+                bounce_off_wall(&mut player.car, &player.shape, &self.shapes.inner)
+            {
+                player.events.push(GameEvent::WallHit {
+                    impact,
+                    side: contact.side(player.car.heading),
+                });
+            }
+        }
+    }
+
+    fn drive_rivals(&mut self, delta: f32, rng: &mut impl Rng) {
+        let tuning = self.tuning();
+        for rival in &mut self.rivals {
+            rival.driver.skill = tuning.skill(rival.skill);
+            let controls = rival
+                .driver
+                .controls(&rival.car, &self.track.racing_line, delta, rng);
+            rival.car.update(&controls, delta);
+            bounce_off_wall(&mut rival.car, &rival.shape, &self.shapes.inner);
+        }
+    }
+
+    /// Separates every pair of overlapping cars, players and rivals alike. Only players take
+    /// damage, through a `CarHit` event.
+    fn resolve_car_contacts(&mut self) {
+        let mut cars: Vec<(&mut Vehicle, &Polygon)> = self
+            .players
+            .iter_mut()
+            .map(|player| (&mut player.car, &player.shape))
+            .chain(
+                self.rivals
+                    .iter_mut()
+                    .map(|rival| (&mut rival.car, &rival.shape)),
+            )
+            .collect();
+
+        let mut hits = Vec::new();
+        for j in 1..cars.len() {
+            let (before, after) = cars.split_at_mut(j);
+            let (b, b_shape) = &mut after[0];
+            for (i, (a, a_shape)) in before.iter_mut().enumerate() {
+                if let Some((contact, impact)) = bump_cars(a, a_shape, b, b_shape) {
+                    hits.push((i, j, contact, impact));
+                }
+            }
+        }
+
+        for (i, j, contact, impact) in hits {
+            let reversed = Contact {
+                normal: -contact.normal,
+                ..contact
+            };
+            for (index, contact) in [(i, contact), (j, reversed)] {
+                // Players come first in the list of cars.
+                if let Some(player) = self.players.get_mut(index) {
+                    player.events.push(GameEvent::CarHit {
+                        impact,
+                        side: contact.side(player.car.heading),
+                    });
+                }
+            }
+        }
+    }
+
+    fn detect_collisions(&mut self) {
+        for i in 0..self.players.len() {
+            let contacts = self.current_contacts(&self.players[i]);
+            let player = &mut self.players[i];
+            let previous = std::mem::replace(&mut player.contacts, contacts.clone());
+
+            // The outer shape covers the whole drivable area, so not overlapping it means the car
+            // has left the track.
+            if contacts.track != previous.track {
+                player.events.push(if contacts.track {
+                    GameEvent::ReturnedToTrack
+                } else {
+                    GameEvent::LeftTrack
+                });
+            }
+
+            for (i, (&now, &before)) in contacts
+                .checkpoints
+                .iter()
+                .zip(&previous.checkpoints)
+                .enumerate()
+            {
+                if now && !before {
+                    player.events.push(GameEvent::CheckpointCrossed(i));
+                }
+            }
+
+            if contacts.finish_line && !previous.finish_line {
+                player.events.push(GameEvent::FinishLineCrossed);
+            }
+
+            // Enemies are collected on the first touch and disappear right away, so only one
+            // player can get each of them.
+            let car = player.polygon();
+            let shapes = &self.shapes;
+            let (collected, remaining): (Vec<Enemy>, Vec<Enemy>) =
+                std::mem::take(&mut self.enemies)
+                    .into_iter()
+                    .partition(|enemy| {
+                        enemy.behaviour.is_collectable() && car.overlaps(&shapes.enemy(enemy))
+                    });
+            self.enemies = remaining;
+
+            for enemy in collected {
+                player.events.push(GameEvent::EnemyCollected {
+                    points: enemy.behaviour.points(),
+                    position: enemy.translation,
+                    label: enemy.label,
+                });
+            }
+
+            // Pickups go the same way.
+            let (collected, remaining): (Vec<Pickup>, Vec<Pickup>) =
+                std::mem::take(&mut self.pickups)
+                    .into_iter()
+                    .partition(|pickup| car.overlaps(&shapes.pickup(pickup)));
+            self.pickups = remaining;
+
+            for pickup in collected {
+                player.events.push(GameEvent::PickupCollected {
+                    label: pickup.label,
+                    kind: pickup.kind,
+                });
+            }
+        }
+    }
+
+    fn update_laps(&mut self, delta: f32) {
+        let checkpoints = self.shapes.checkpoints.len();
+        let laps = self.laps();
+        for player in &mut self.players {
+            if player.is_done() {
+                continue;
+            }
+            player.lap_time += delta;
+
+            let mut completed_lap = None;
+            for event in &player.events {
+                match event {
+                    // Checkpoints only count when crossed in order, so cutting across the track
+                    // does not complete a lap.
+                    GameEvent::CheckpointCrossed(index) if *index == player.next_checkpoint => {
+                        player.next_checkpoint += 1;
+                        player.splits.push(self.time);
+                    }
+                    GameEvent::FinishLineCrossed if player.next_checkpoint == checkpoints => {
+                        let lap_time = player.lap_time;
+                        if player.best_lap.is_none_or(|best| lap_time < best) {
+                            player.best_lap = Some(lap_time);
+                        }
+
+                        player.lap += 1;
+                        player.lap_time = 0.0;
+                        player.next_checkpoint = 0;
+                        player.splits.push(self.time);
+                        if laps.is_some_and(|laps| player.lap > laps) {
+                            player.finish_time = Some(self.time);
+                        }
+                        completed_lap = Some(lap_time);
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some(time) = completed_lap {
+                player.events.push(GameEvent::LapCompleted { time });
+            }
+        }
+    }
+
+    /// Rivals follow the same gate rules as the player, without the events. Gates are only checked
+    /// in driving order, so staying on one for several steps counts once. Finished rivals keep
+    /// driving, but their race is over.
+    fn update_rival_laps(&mut self) {
+        let checkpoints = &self.shapes.checkpoints;
+        let laps = self.laps();
+        for rival in &mut self.rivals {
+            if rival.finish_time.is_some() {
+                continue;
+            }
+
+            let car = rival
+                .shape
+                .transformed(rival.car.position, rival.car.heading, CAR_SCALE);
+
+            if let Some(checkpoint) = checkpoints.get(rival.next_checkpoint) {
+                if car.overlaps(checkpoint) {
+                    rival.next_checkpoint += 1;
+                    rival.splits.push(self.time);
+                }
+            } else if car.overlaps(&self.shapes.finish_line) {
+                rival.lap += 1;
+                rival.next_checkpoint = 0;
+                rival.splits.push(self.time);
+                if laps.is_some_and(|laps| rival.lap > laps) {
+                    rival.finish_time = Some(self.time);
+                }
+            }
+        }
+    }
+
+    /// Every car in race order.
+    pub fn standings(&self) -> Vec<Standing> {
+        let centerline = &self.shapes.centerline;
+        let players = self.players.iter().map(|player| Standing {
+            label: player.label.clone(),
+            name: player.name.clone(),
+            progress: Standing::progress(
+                player.lap,
+                player.next_checkpoint,
+                centerline.lap_fraction(player.car.position),
+            ),
+            finish_time: player.finish_time,
+            gap: None,
+            splits: player.splits.clone(),
+        });
+        let rivals = self.rivals.iter().map(|rival| Standing {
+            label: rival.label.clone(),
+            name: format!("{:?}", rival.preset),
+            progress: Standing::progress(
+                rival.lap,
+                rival.next_checkpoint,
+                centerline.lap_fraction(rival.car.position),
+            ),
+            finish_time: rival.finish_time,
+            gap: None,
+            splits: rival.splits.clone(),
+        });
+
+        race::rank(players.chain(rivals).collect())
+    }
+
+    /// Rewards grow with the combo and the multiplier power-up, penalties are taken as they are.
+    /// Hitting the wall ends the combo and spoils the clean lap bonus.
+    fn update_score(&mut self, delta: f32) {
+        for player in &mut self.players {
+            player.combo.tick(delta);
+
+            let mut scored = Vec::new();
+            for event in &player.events {
+                match *event {
+                    GameEvent::EnemyCollected {
+                        points, position, ..
+                    } => {
+                        let multiplier = if points > 0 {
+                            player.combo.collect() * player.power_ups.score_multiplier()
+                        } else {
+                            1
+                        };
+                        scored.push((points * multiplier, multiplier, position));
+                    }
+                    GameEvent::WallHit { .. } => {
+                        player.combo.reset();
+                        player.clean_lap = false;
+                    }
+                    GameEvent::LapCompleted { .. } => {
+                        if player.clean_lap {
+                            scored.push((CLEAN_LAP_BONUS, 1, player.car.position));
+                        }
+                        player.clean_lap = true;
+                    }
+                    _ => {}
+                }
+            }
+
+            for (points, multiplier, position) in scored {
+                player.score += points;
+                player.events.push(GameEvent::Scored {
+                    points,
+                    multiplier,
+                    position,
+                });
+            }
+        }
+    }
+
+    /// Repair kits act right away, everything else starts its timer.
+    fn use_pickups(&mut self) {
+        for player in &mut self.players {
+            for event in &player.events {
+                match event {
+                    GameEvent::PickupCollected {
+                        kind: Kind::Repair, ..
+                    } => player.health = (player.health + pickup::REPAIR_HEALTH).min(MAX_HEALTH),
+                    GameEvent::PickupCollected { kind, .. } => player.power_ups.start(*kind),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn update_health(&mut self, delta: f32) {
+        let rates = self.damage_rates.scaled(self.tuning().damage);
+        for player in &mut self.players {
+            // Impacts and being off the track are independent, and all cost health when they
+            // happen at the same time.
+            let mut damage = 0.0;
+            for event in &player.events {
+                match event {
+                    GameEvent::WallHit { .. } if player.power_ups.is_active(Kind::Shield) => {}
+                    GameEvent::WallHit { impact, .. } => damage += impact * rates.wall_impact,
+                    GameEvent::CarHit { impact, .. } => damage += impact * rates.car_impact,
+                    GameEvent::LeftTrack => player.off_track = true,
+                    GameEvent::ReturnedToTrack => player.off_track = false,
+                    _ => {}
+                }
+            }
+
+            if player.off_track {
+                damage += rates.off_track * delta;
+            }
+            player.health = (player.health - damage).max(0.0);
+        }
+    }
+
+    fn spawn_enemies(&mut self, delta: f32, rng: &mut impl Rng) {
+        self.spawn_timer -= delta;
+        if self.spawn_timer > 0.0 {
+            return;
+        }
+        let tuning = self.tuning();
+        self.spawn_timer = rng.gen_range(tuning.spawn_interval.0..tuning.spawn_interval.1);
+
+        if self.capped_enemies() >= tuning.max_enemies {
+            return;
+        }
+
+        let (zone, position) = match self.spawn_spot(rng) {
+            Some(spot) => spot,
+            None => return,
+        };
+        let behaviour = random_behaviour(&zone, position, &tuning, rng);
+        if behaviour == Behaviour::Pushable && self.cones() >= MAX_CONES {
+            return;
+        }
+
+        let label = format!("enemy_{}", self.next_enemy_id);
+        self.next_enemy_id += 1;
+
+        let mut enemy = Enemy::new(label, behaviour, position);
+        enemy.spawned = true;
+        self.enemies.push(enemy);
+    }
+
+    /// Enemies that count towards the enemy cap of the difficulty. Those placed by the track are
+    /// there whatever the cap.
+    fn capped_enemies(&self) -> usize {
+        self.enemies
+            .iter()
+            .filter(|enemy| enemy.spawned && enemy.behaviour != Behaviour::Pushable)
+            .count()
+    }
+
+    fn cones(&self) -> usize {
+        self.enemies
+            .iter()
+            .filter(|enemy| enemy.behaviour == Behaviour::Pushable)
+            .count()
+    }
+
+    /// Works like `spawn_enemies`, on a slower timer of its own.
+    fn spawn_pickups(&mut self, delta: f32, rng: &mut impl Rng) {
+        self.pickup_timer -= delta;
+        if self.pickup_timer > 0.0 {
+            return;
+        }
+        self.pickup_timer = rng.gen_range(6.0..12.0);
+
+        if self.pickups.len() >= MAX_PICKUPS {
+            return;
+        }
+
+        let (_, position) = match self.spawn_spot(rng) {
+            Some(spot) => spot,
+            None => return,
+        };
+        let kind = match Kind::ALL.choose_weighted(rng, |kind| kind.spawn_weight()) {
+            Ok(&kind) => kind,
+            Err(_) => return,
+        };
+
+        let label = format!("pickup_{}", self.next_pickup_id);
+        self.next_pickup_id += 1;
+
+        self.pickups.push(Pickup {
+            label,
+            kind,
+            position,
+        });
+    }
+
+    /// Random point in one of the spawn zones that keeps clear of the players and of everything
+    /// spawned before, along with its zone.
+    fn spawn_spot(&self, rng: &mut impl Rng) -> Option<(SpawnZone, Vec2)> {
+        let taken: Vec<Vec2> = self
+            .players
+            .iter()
+            .map(|player| player.car.position)
+            .chain(self.enemies.iter().map(|enemy| enemy.translation))
+            .chain(self.pickups.iter().map(|pickup| pickup.position))
+            .collect();
+
+        (0..SPAWN_ATTEMPTS)
+            .filter_map(|_| {
+                let zone = self.track.spawn_zones.choose(rng)?;
+                Some((*zone, zone.random_point(rng)))
+            })
+            .find(|(_, spot)| {
+                taken
+                    .iter()
+                    .all(|position| position.distance(*spot) > MIN_SPAWN_DISTANCE)
+            })
+    }
+}
+
+/// Picks a behaviour by the difficulty's weights, mostly things worth collecting with the odd
+/// hazard, chaser or cone in between. Patrols stay inside the zone they spawned in.
+fn random_behaviour(
+    zone: &SpawnZone,
+    position: Vec2,
+    tuning: &Tuning,
+    rng: &mut impl Rng,
+) -> Behaviour {
+    let index = (0..tuning.behaviour_weights.len())
+        .collect::<Vec<_>>()
+        .choose_weighted(rng, |&i| tuning.behaviour_weights[i])
+        .copied()
+        .unwrap_or(0);
+    let speed = tuning.enemy_speed;
+
+    match index {
+        0 => Behaviour::Oscillating {
+            direction: rng.gen_range(0.0..std::f32::consts::TAU),
+            amplitude: rng.gen_range(10.0..50.0) * tuning.enemy_reach,
+            frequency: rng.gen_range(0.5..2.0) * speed,
+            phase: rng.gen_range(0.0..std::f32::consts::TAU),
+        },
+        1 => Behaviour::Patrolling {
+            path: vec![zone.random_point(rng), position],
+            speed: rng.gen_range(40.0..100.0) * speed,
+        },
+        2 => Behaviour::Chasing {
+            speed: rng.gen_range(60.0..100.0) * speed,
+        },
+        3 => Behaviour::Hazard,
+        _ => Behaviour::Pushable,
+    }
+}
+
+/// Keeps a car out of the inner wall: pushes it back out along the contact normal and bounces
+/// off the part of the velocity that went into the wall. Returns the contact and the speed into
+/// the wall for impacts hard enough to count.
+fn bounce_off_wall(car: &mut Vehicle, shape: &Polygon, wall: &Polygon) -> Option<(Contact, f32)> {
+    let contact = shape
+        .transformed(car.position, car.heading, CAR_SCALE)
+        .contact(wall)?;
+
+    // A little extra, so the car ends up just clear of the wall instead of exactly touching it.
+    car.position += contact.normal * (contact.depth + 0.01);
+
+    let into_wall = -car.velocity.dot(contact.normal);
+    if into_wall <= 0.0 {
+        return None;
+    }
+
+    let along_wall = car.velocity + contact.normal * into_wall;
+    if into_wall < MIN_IMPACT_SPEED {
+        car.velocity = along_wall;
+        return None;
+    }
+
+    car.velocity = along_wall * WALL_FRICTION + contact.normal * into_wall * WALL_RESTITUTION;
+    Some((contact, into_wall))
+}
+
+/// Pushes two overlapping cars apart, half the way each, and bounces them off each other like two
+/// equally heavy bodies. Returns the contact as seen from `a` and the closing speed, for impacts
+/// hard enough to count.
+fn bump_cars(
+    a: &mut Vehicle,
+    a_shape: &Polygon,
+    b: &mut Vehicle,
+    b_shape: &Polygon,
+) -> Option<(Contact, f32)> {
+    let contact = a_shape
+        .transformed(a.position, a.heading, CAR_SCALE)
+        .contact(&b_shape.transformed(b.position, b.heading, CAR_SCALE))?;
+
+    let push = contact.normal * (contact.depth / 2.0 + 0.01);
+    a.position += push;
+    b.position -= push;
+
+    let closing = (b.velocity - a.velocity).dot(contact.normal);
+    if closing <= 0.0 {
+        return None;
+    }
+
+    let exchange = contact.normal * closing * (1.0 + CAR_RESTITUTION) / 2.0;
+    a.velocity += exchange;
+    b.velocity -= exchange;
+
+    if closing < MIN_IMPACT_SPEED {
+        return None;
+    }
+    Some((contact, closing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Side;
+    use crate::track::DEFAULT_TRACK;
+
+    const FIXED_DELTA: f32 = 1.0 / 60.0;
+
+    /// Corners of the racing line around track01, clockwise from the start.
+    const LAP_WAYPOINTS: [(f32, f32); 9] = [
+        (550.0, 256.0),
+        (683.0, 120.0),
+        (683.0, -160.0),
+        (550.0, -297.0),
+        (-550.0, -297.0),
+        (-690.0, -160.0),
+        (-690.0, 120.0),
+        (-550.0, 256.0),
+        (0.0, 256.0),
+    ];
+
+    fn track() -> Track {
+        let mut track = Track::load(DEFAULT_TRACK).unwrap();
+        // Random spawns would make scores depend on the seed.
+        track.spawn_zones.clear();
+        track
+    }
+
+    /// A single player alone on the track, so rivals do not get in the way of scripted driving.
+    fn simulation() -> Simulation {
+        let mut track = track();
+        track.grid.clear();
+        Simulation::new(track, &[CarPreset::Green]).unwrap()
+    }
+
+    fn race() -> Simulation {
+        Simulation::new(track(), &[CarPreset::Green]).unwrap()
+    }
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(7)
+    }
+
+    /// Scripted driver: full throttle towards the next waypoint, lifting off for sharp turns.
+    fn controls_towards(car: &Vehicle, target: Vec2) -> Controls {
+        let to_target = target - car.position;
+        let angle = car.forward().angle_between(to_target);
+        Controls {
+            throttle: if angle.abs() < 0.5 { 1.0 } else { 0.3 },
+            brake: if angle.abs() > 0.8 && car.speed() > 200.0 {
+                1.0
+            } else {
+                0.0
+            },
+            steering: (angle * 3.0).clamp(-1.0, 1.0),
+        }
+    }
+
+    fn drive_lap(simulation: &mut Simulation, rng: &mut StdRng) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        for &(x, y) in &LAP_WAYPOINTS {
+            let target = Vec2::new(x, y);
+            let mut steps = 0;
+            while simulation.players[0].car.position.distance(target) > 60.0 {
+                let controls = controls_towards(&simulation.players[0].car, target);
+                simulation.step(&[controls], FIXED_DELTA, rng);
+                events.extend(simulation.players[0].events.iter().cloned());
+
+                steps += 1;
+                assert!(steps < 60 * 20, "car never reached waypoint {:?}", target);
+            }
+        }
+
+        // Drive on until the finish line, which is just behind the start.
+        let mut steps = 0;
+        while simulation.players[0].lap == 1 && steps < 60 * 5 {
+            let controls = controls_towards(&simulation.players[0].car, Vec2::new(200.0, 256.0));
+            simulation.step(&[controls], FIXED_DELTA, rng);
+            events.extend(simulation.players[0].events.iter().cloned());
+            steps += 1;
+        }
+
+        events
+    }
+
+    #[test]
+    fn clean_lap_counts_and_keeps_health() {
+        let mut simulation = simulation();
+        let mut rng = rng();
+
+        let events = drive_lap(&mut simulation, &mut rng);
+
+        assert_eq!(simulation.players[0].lap, 2);
+        assert!(simulation.players[0].best_lap.is_some());
+        assert_eq!(simulation.players[0].health, MAX_HEALTH);
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, GameEvent::WallHit { .. })));
+        assert!(!events.contains(&GameEvent::LeftTrack));
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, GameEvent::EnemyCollected { .. })));
+        let scored: Vec<i32> = events
+            .iter()
+            .filter_map(|event| match event {
+                GameEvent::Scored { points, .. } => Some(*points),
+                _ => None,
+            })
+            .collect();
+        assert!(scored.contains(&CLEAN_LAP_BONUS));
+        assert_eq!(simulation.players[0].score, scored.iter().sum::<i32>());
+    }
+
+    #[test]
+    fn finish_line_without_checkpoints_is_not_a_lap() {
+        let mut simulation = simulation();
+        let mut rng = rng();
+
+        // Reverse over the finish line and drive forward across it again.
+        simulation.players[0].car.position = Vec2::new(-150.0, 256.0);
+        for _ in 0..120 {
+            let controls = Controls {
+                throttle: 1.0,
+                ..Default::default()
+            };
+            simulation.step(&[controls], FIXED_DELTA, &mut rng);
+        }
+
+        assert!(simulation.players[0].car.position.x > 0.0);
+        assert_eq!(simulation.players[0].lap, 1);
+        assert_eq!(simulation.players[0].best_lap, None);
+    }
+
+    #[test]
+    fn wall_pushes_the_car_out_and_damage_scales_with_impact_speed() {
+        let hit_wall = |speed: f32| {
+            let mut simulation = simulation();
+            let mut rng = rng();
+
+            // Heading straight down into the top edge of the infield.
+            simulation.players[0].car.position = Vec2::new(0.0, 160.0);
+            simulation.players[0].car.heading = -std::f32::consts::FRAC_PI_2;
+            simulation.players[0].car.velocity = Vec2::new(0.0, -speed);
+            simulation.step(&[], FIXED_DELTA, &mut rng);
+
+            let events = simulation.players[0].events.clone();
+            (simulation, events)
+        };
+
+        let (slow, slow_events) = hit_wall(100.0);
+        let (fast, fast_events) = hit_wall(400.0);
+
+        for simulation in [&slow, &fast] {
+            assert!(!simulation.players[0]
+                .polygon()
+                .overlaps(&simulation.shapes.inner));
+            assert!(
+                simulation.players[0].car.velocity.y > 0.0,
+                "car should bounce back"
+            );
+        }
+        assert!(matches!(
+            slow_events[0],
+            GameEvent::WallHit {
+                side: Side::Front,
+                ..
+            }
+        ));
+        assert!(matches!(fast_events[0], GameEvent::WallHit { .. }));
+
+        let slow_damage = MAX_HEALTH - slow.players[0].health;
+        let fast_damage = MAX_HEALTH - fast.players[0].health;
+        assert!(slow_damage > 0.0);
+        assert!((fast_damage / slow_damage - 4.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn resting_against_the_wall_is_free() {
+        let mut simulation = simulation();
+        let mut rng = rng();
+
+        simulation.players[0].car.position = Vec2::new(0.0, 150.0);
+        simulation.players[0].car.heading = -std::f32::consts::FRAC_PI_2;
+        for _ in 0..60 {
+            let controls = Controls {
+                throttle: 0.1,
+                ..Default::default()
+            };
+            simulation.step(&[controls], FIXED_DELTA, &mut rng);
+        }
+
+        assert_eq!(simulation.players[0].health, MAX_HEALTH);
+        assert!(!simulation.players[0]
+            .polygon()
+            .overlaps(&simulation.shapes.inner));
+    }
+
+    #[test]
+    fn leaving_the_track_drains_health_at_off_track_rate() {
+        let mut simulation = simulation();
+        let mut rng = rng();
+
+        simulation.players[0].car.position = Vec2::new(0.0, 600.0);
+        for _ in 0..60 {
+            simulation.step(&[], FIXED_DELTA, &mut rng);
+        }
+
+        assert!(simulation.players[0].off_track);
+        assert!((simulation.players[0].health - (MAX_HEALTH - OFF_TRACK_RATE)).abs() < 0.01);
+
+        // Back on the track the damage stops.
+        simulation.players[0].car.position = simulation.track.start.position;
+        simulation.step(&[], FIXED_DELTA, &mut rng);
+        let health = simulation.players[0].health;
+        simulation.step(&[], FIXED_DELTA, &mut rng);
+
+        assert!(!simulation.players[0].off_track);
+        assert_eq!(simulation.players[0].health, health);
+    }
+
+    #[test]
+    fn collected_enemy_scores_and_is_removed() {
+        let mut simulation = simulation();
+        let mut rng = rng();
+
+        let enemy = simulation.enemies[0].clone();
+        simulation.players[0].car.position = enemy.translation;
+        simulation.step(&[], FIXED_DELTA, &mut rng);
+
+        let points = enemy.behaviour.points();
+        assert_eq!(simulation.players[0].score, points);
+        assert!(simulation.players[0].events.iter().any(|event| matches!(
+            event,
+            GameEvent::EnemyCollected { label, points: p, .. } if *label == enemy.label && *p == points
+        )));
+        assert!(simulation.enemies.iter().all(|e| e.label != enemy.label));
+    }
+
+    #[test]
+    fn cones_stay_inside_the_outer_edge() {
+        let mut simulation = simulation();
+        let mut rng = rng();
+
+        let cone = simulation
+            .enemies
+            .iter_mut()
+            .find(|enemy| enemy.behaviour == Behaviour::Pushable)
+            .unwrap();
+        cone.velocity = Vec2::new(0.0, 2000.0);
+        let label = cone.label.clone();
+        for _ in 0..120 {
+            simulation.step(&[], FIXED_DELTA, &mut rng);
+        }
+
+        let cone = simulation
+            .enemies
+            .iter()
+            .find(|e| e.label == label)
+            .unwrap();
+        assert!(simulation
+            .shapes
+            .outer
+            .contains(cone.translation - Vec2::new(0.0, 1.0)));
+    }
+
+    #[test]
+    fn pickups_repair_shield_and_multiply() {
+        let mut simulation = simulation();
+        let mut rng = rng();
+        let drop = |simulation: &mut Simulation, kind| {
+            simulation.pickups.push(Pickup {
+                label: format!("pickup_{:?}", kind),
+                kind,
+                position: simulation.players[0].car.position,
+            });
+        };
+
+        simulation.players[0].health = 50.0;
+        drop(&mut simulation, Kind::Repair);
+        drop(&mut simulation, Kind::Shield);
+        drop(&mut simulation, Kind::Multiplier);
+        simulation.step(&[], FIXED_DELTA, &mut rng);
+        assert_eq!(simulation.players[0].health, 50.0 + pickup::REPAIR_HEALTH);
+        assert!(simulation.pickups.is_empty());
+
+        // Shielded, the wall only bounces the car.
+        simulation.players[0].car.position = Vec2::new(0.0, 160.0);
+        simulation.players[0].car.heading = -std::f32::consts::FRAC_PI_2;
+        simulation.players[0].car.velocity = Vec2::new(0.0, -400.0);
+        simulation.step(&[], FIXED_DELTA, &mut rng);
+        assert!(matches!(
+            simulation.players[0].events[0],
+            GameEvent::WallHit { .. }
+        ));
+        assert_eq!(simulation.players[0].health, 50.0 + pickup::REPAIR_HEALTH);
+
+        let enemy = simulation.enemies[0].clone();
+        simulation.players[0].car.position = enemy.translation;
+        simulation.step(&[], FIXED_DELTA, &mut rng);
+        assert_eq!(simulation.players[0].score, enemy.behaviour.points() * 2);
+
+        for _ in 0..60 * 10 {
+            simulation.step(&[], FIXED_DELTA, &mut rng);
+        }
+        assert_eq!(simulation.players[0].power_ups.remaining().count(), 0);
+    }
+
+    #[test]
+    fn spawning_stops_at_the_enemy_cap_of_the_difficulty() {
+        for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+            let mut simulation =
+                Simulation::new(Track::load(DEFAULT_TRACK).unwrap(), &[CarPreset::Green]).unwrap();
+            simulation.difficulty = difficulty;
+            let max_enemies = simulation.tuning().max_enemies;
+            let mut rng = rng();
+
+            for _ in 0..60 * 60 {
+                simulation.step(&[], FIXED_DELTA, &mut rng);
+                assert!(simulation.capped_enemies() <= max_enemies);
+                assert!(simulation.cones() <= MAX_CONES);
+            }
+
+            assert_eq!(simulation.capped_enemies(), max_enemies, "{:?}", difficulty);
+        }
+    }
+
+    #[test]
+    fn rivals_drive_around_the_track() {
+        let mut simulation = race();
+        let mut rng = rng();
+        assert_eq!(simulation.rivals.len(), RIVALS.len());
+
+        let checkpoints: Vec<Vec2> = simulation
+            .track
+            .checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.position)
+            .collect();
+        let mut closest = vec![vec![f32::MAX; checkpoints.len()]; simulation.rivals.len()];
+
+        for _ in 0..60 * 20 {
+            simulation.step(&[], FIXED_DELTA, &mut rng);
+
+            for (rival, closest) in simulation.rivals.iter().zip(&mut closest) {
+                for (checkpoint, closest) in checkpoints.iter().zip(closest.iter_mut()) {
+                    *closest = closest.min(rival.car.position.distance(*checkpoint));
+                }
+            }
+        }
+
+        for (rival, closest) in simulation.rivals.iter().zip(&closest) {
+            assert!(
+                closest.iter().all(|&distance| distance < 120.0),
+                "{} missed a checkpoint: {:?}",
+                rival.label,
+                closest
+            );
+        }
+    }
+
+    #[test]
+    fn rivals_finish_the_race_ahead_of_a_parked_player() {
+        let mut simulation = race();
+        let mut rng = rng();
+
+        let mut steps = 0;
+        while simulation
+            .rivals
+            .iter()
+            .any(|rival| rival.finish_time.is_none())
+        {
+            simulation.step(&[], FIXED_DELTA, &mut rng);
+            steps += 1;
+            assert!(steps < 60 * 90, "rivals never finished the race");
+        }
+
+        let standings = simulation.standings();
+        assert_eq!(standings.len(), RIVALS.len() + 1);
+        assert_eq!(standings.last().unwrap().label, "player_1");
+        assert!(standings[..RIVALS.len()]
+            .windows(2)
+            .all(|pair| pair[0].finish_time <= pair[1].finish_time));
+        assert!(standings[1..RIVALS.len()]
+            .iter()
+            .all(|standing| standing.gap.is_some_and(|gap| gap >= 0.0)));
+    }
+
+    #[test]
+    fn endless_runs_go_on_past_the_last_lap() {
+        let mut simulation = race();
+        simulation.difficulty = Difficulty::Endless;
+        let mut rng = rng();
+
+        let mut steps = 0;
+        while simulation.rivals.iter().all(|rival| rival.lap <= RACE_LAPS) {
+            simulation.step(&[], FIXED_DELTA, &mut rng);
+            steps += 1;
+            assert!(steps < 60 * 90, "rivals never got past the last lap");
+        }
+
+        assert!(simulation
+            .rivals
+            .iter()
+            .all(|rival| rival.finish_time.is_none()));
+        assert!(!simulation.is_over());
+    }
+
+    #[test]
+    fn players_need_a_start_pose_each() {
+        let mut track = track();
+        track.grid.clear();
+
+        assert!(Simulation::new(track.clone(), &[CarPreset::Green]).is_ok());
+        assert!(Simulation::new(track, &[CarPreset::Green, CarPreset::Red]).is_err());
+    }
+
+    #[test]
+    fn players_bump_each_other_and_both_take_damage() {
+        let mut simulation = Simulation::new(track(), &[CarPreset::Green, CarPreset::Red]).unwrap();
+        let mut rng = rng();
+        assert_eq!(simulation.rivals.len(), simulation.track.grid.len() - 1);
+        assert!(simulation
+            .rivals
+            .iter()
+            .all(|rival| rival.preset != CarPreset::Red));
+
+        // Head on, on the straight behind the infield.
+        simulation.players[0].car.position = Vec2::new(-450.0, 300.0);
+        simulation.players[0].car.velocity = Vec2::new(200.0, 0.0);
+        simulation.players[1].car.position = Vec2::new(-400.0, 300.0);
+        simulation.players[1].car.heading = std::f32::consts::PI;
+        simulation.players[1].car.velocity = Vec2::new(-200.0, 0.0);
+        simulation.step(&[], FIXED_DELTA, &mut rng);
+
+        let [first, second] = &simulation.players[..] else {
+            panic!("expected two players");
+        };
+        assert!(!first.polygon().overlaps(&second.polygon()));
+        assert!(first.car.velocity.x < 0.0 && second.car.velocity.x > 0.0);
+        for player in [first, second] {
+            assert!(player.events.iter().any(|event| matches!(
+                event,
+                GameEvent::CarHit {
+                    side: Side::Front,
+                    ..
+                }
+            )));
+            assert!(player.health < MAX_HEALTH);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the game keeps the files it writes, like bindings and high scores. Platforms without a
+/// data directory get a `user` directory next to the assets instead.
+pub fn user_file(name: &str) -> PathBuf {
+    dirs::data_dir()
+        .map(|directory| directory.join("boring_game"))
+        .unwrap_or_else(|| PathBuf::from("user"))
+        .join(name)
+}
+
+/// Writes `value` as RON, creating the directory first if needed.
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let contents = ron::ser::to_string_pretty(value, Default::default())
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    write(path, &contents)
+}
+
+/// Writes `contents`, creating the directory first if needed.
+pub fn write(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Could not create {}: {}", directory.display(), e))?;
+    }
+    fs::write(path, contents).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
@@ -0,0 +1,86 @@
+/// Length of one simulation step in seconds. Gameplay only ever advances by this much, so the
+/// same input gives the same race however fast the game is drawn.
+pub const TIMESTEP: f32 = 1.0 / 120.0;
+/// Frames taking longer than this many steps slow the game down instead of catching up, so one
+/// long hitch does not turn into a burst of steps that makes the next frame slow too.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// Turns frame times of any length into a whole number of fixed simulation steps.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FixedTimestep {
+    /// Time that has passed but not been simulated yet, less than one step after `steps`.
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+
+    /// Adds a frame of `delta` seconds and returns how many steps to simulate for it.
+    pub fn steps(&mut self, delta: f32) -> u32 {
+        self.accumulator += delta;
+        let steps = (self.accumulator / TIMESTEP) as u32;
+        self.accumulator -= steps as f32 * TIMESTEP;
+
+        if steps > MAX_STEPS_PER_FRAME {
+            self.accumulator = 0.0;
+            return MAX_STEPS_PER_FRAME;
+        }
+        steps
+    }
+
+    /// How far the clock is into the next step, between 0 and 1. Sprites are drawn this far
+    /// between the last two steps, so motion looks smooth when frames and steps do not line up.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / TIMESTEP).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Simulation;
+    use crate::track::{Track, DEFAULT_TRACK};
+    use crate::vehicle::{CarPreset, Controls};
+    use rand::prelude::*;
+
+    #[test]
+    fn frames_are_split_into_whole_steps() {
+        let mut timestep = FixedTimestep::default();
+
+        assert_eq!(timestep.steps(TIMESTEP * 0.5), 0);
+        assert!((timestep.alpha() - 0.5).abs() < 0.001);
+        assert_eq!(timestep.steps(TIMESTEP * 2.0), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 0.001);
+        assert_eq!(timestep.steps(1.0), MAX_STEPS_PER_FRAME);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn frame_rate_does_not_change_the_race() {
+        let race = |frame_time: f32| {
+            let track = Track::load(DEFAULT_TRACK).unwrap();
+            let mut sim = Simulation::new(track, &[CarPreset::Green]).unwrap();
+            let mut rng = StdRng::seed_from_u64(7);
+            let mut timestep = FixedTimestep::default();
+            let controls = [Controls {
+                throttle: 1.0,
+                brake: 0.0,
+                steering: 0.3,
+            }];
+
+            let mut remaining = 600;
+            while remaining > 0 {
+                let steps = timestep.steps(frame_time).min(remaining);
+                for _ in 0..steps {
+                    sim.step(&controls, TIMESTEP, &mut rng);
+                }
+                remaining -= steps;
+            }
+            format!("{:?}", sim)
+        };
+
+        assert_eq!(race(1.0 / 30.0), race(1.0 / 144.0));
+    }
+}
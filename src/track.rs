@@ -0,0 +1,99 @@
+use crate::enemy::Behaviour;
+use crate::geometry::Polygon;
+use rand::prelude::*;
+use rusty_engine::prelude::*;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_TRACK: &str = "track01";
+
+const TRACK_DIRECTORY: &str = "assets/track";
+
+/// One visual layer of a track: the image shown on screen and the polygon used for collisions.
+/// Both paths are relative to the `assets` directory, like every other sprite path.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TrackLayer {
+    pub image: String,
+    pub collider: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct StartPose {
+    pub position: Vec2,
+    pub rotation: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnemyPlacement {
+    pub position: Vec2,
+    pub behaviour: Behaviour,
+}
+
+/// Axis aligned rectangle on the track surface where new enemies may appear.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct SpawnZone {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl SpawnZone {
+    pub fn random_point(&self, rng: &mut impl Rng) -> Vec2 {
+        Vec2::new(
+            self.min.x + rng.gen::<f32>() * (self.max.x - self.min.x),
+            self.min.y + rng.gen::<f32>() * (self.max.y - self.min.y),
+        )
+    }
+}
+
+/// Invisible rectangle the player has to drive through, used for checkpoints and the finish line.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct Gate {
+    pub position: Vec2,
+    pub size: Vec2,
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+impl Gate {
+    pub fn polygon(&self) -> Polygon {
+        Polygon::rectangle(self.size).transformed(self.position, self.rotation, 1.0)
+    }
+}
+
+/// Track manifest, loaded from `assets/track/<name>.ron`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Track {
+    #[serde(skip)]
+    pub name: String,
+    pub inner: TrackLayer,
+    pub outer: TrackLayer,
+    pub start: StartPose,
+    pub finish_line: Gate,
+    /// Checkpoints in the order they have to be crossed during a lap.
+    pub checkpoints: Vec<Gate>,
+    pub enemies: Vec<EnemyPlacement>,
+    pub spawn_zones: Vec<SpawnZone>,
+    /// Waypoints computer drivers steer through, in driving order. The last one leads back to
+    /// the first.
+    #[serde(default)]
+    pub racing_line: Vec<Vec2>,
+    /// Starting spots behind `start`, filled by the other local players first and then by the
+    /// computer drivers. Every spot left over gets a rival, up to the number of rival cars.
+    #[serde(default)]
+    pub grid: Vec<StartPose>,
+}
+
+impl Track {
+    pub fn load(name: &str) -> Result<Track, String> {
+        let path = Path::new(TRACK_DIRECTORY).join(format!("{}.ron", name));
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read track {}: {}", path.display(), e))?;
+
+        let mut track: Track = ron::from_str(&contents)
+            .map_err(|e| format!("Could not parse track {}: {}", path.display(), e))?;
+        track.name = name.to_string();
+
+        Ok(track)
+    }
+}
@@ -0,0 +1,241 @@
+use rusty_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How a car accelerates, brakes and corners. Every preset has its own, see `CarPreset::handling`.
+/// Speeds are in pixels per second, accelerations in pixels per second squared.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Handling {
+    pub acceleration: f32,
+    pub braking: f32,
+    pub reverse_acceleration: f32,
+    pub top_speed: f32,
+    pub reverse_speed: f32,
+    /// Constant deceleration while coasting.
+    pub rolling_resistance: f32,
+    /// Air drag, multiplied by the square of the speed.
+    pub drag: f32,
+    /// Turn rate in radians per second at full steering lock.
+    pub steering_rate: f32,
+    /// Below this speed steering is scaled down, so a standing car cannot spin on the spot.
+    pub steering_full_speed: f32,
+    /// How quickly sideways sliding is cancelled. Lower values make the car drift more.
+    pub grip: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum CarPreset {
+    #[default]
+    Green,
+    Red,
+    Blue,
+    Yellow,
+    Black,
+}
+
+impl CarPreset {
+    pub fn sprite(self) -> SpritePreset {
+        match self {
+            CarPreset::Green => SpritePreset::RacingCarGreen,
+            CarPreset::Red => SpritePreset::RacingCarRed,
+            CarPreset::Blue => SpritePreset::RacingCarBlue,
+            CarPreset::Yellow => SpritePreset::RacingCarYellow,
+            CarPreset::Black => SpritePreset::RacingCarBlack,
+        }
+    }
+
+    /// See-through version of the car sprite for ghosts, relative to the `assets` directory.
+    /// Sprites cannot be made transparent at runtime, so these are separate images.
+    pub fn ghost_sprite(self) -> &'static str {
+        match self {
+            CarPreset::Green => "sprite/racing/car_green_ghost.png",
+            CarPreset::Red => "sprite/racing/car_red_ghost.png",
+            CarPreset::Blue => "sprite/racing/car_blue_ghost.png",
+            CarPreset::Yellow => "sprite/racing/car_yellow_ghost.png",
+            CarPreset::Black => "sprite/racing/car_black_ghost.png",
+        }
+    }
+
+    /// Collider of the car sprite, relative to the `assets` directory.
+    pub fn collider(self) -> &'static str {
+        match self {
+            CarPreset::Green => "sprite/racing/car_green.collider",
+            CarPreset::Red => "sprite/racing/car_red.collider",
+            CarPreset::Blue => "sprite/racing/car_blue.collider",
+            CarPreset::Yellow => "sprite/racing/car_yellow.collider",
+            CarPreset::Black => "sprite/racing/car_black.collider",
+        }
+    }
+
+    pub fn handling(self) -> Handling {
+        let balanced = Handling {
+            acceleration: 300.0,
+            braking: 600.0,
+            reverse_acceleration: 200.0,
+            top_speed: 500.0,
+            reverse_speed: 150.0,
+            rolling_resistance: 60.0,
+            drag: 0.0004,
+            steering_rate: 3.0,
+            steering_full_speed: 150.0,
+            grip: 8.0,
+        };
+
+        match self {
+            CarPreset::Green => balanced,
+            // Quick off the line, but slides around in corners.
+            CarPreset::Red => Handling {
+                acceleration: 380.0,
+                grip: 5.0,
+                ..balanced
+            },
+            // Sticks to the road, but has a lower top speed.
+            CarPreset::Blue => Handling {
+                top_speed: 440.0,
+                grip: 12.0,
+                steering_rate: 3.4,
+                ..balanced
+            },
+            // Fastest on the straights, slow to turn.
+            CarPreset::Yellow => Handling {
+                top_speed: 580.0,
+                steering_rate: 2.5,
+                ..balanced
+            },
+            // Heavy: slow to accelerate, brakes hard.
+            CarPreset::Black => Handling {
+                acceleration: 250.0,
+                braking: 750.0,
+                top_speed: 530.0,
+                ..balanced
+            },
+        }
+    }
+}
+
+/// Driver input for one update. `throttle` and `brake` are in `0.0..=1.0`, `steering` is in
+/// `-1.0..=1.0` where positive values turn left.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Controls {
+    pub throttle: f32,
+    pub brake: f32,
+    pub steering: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Vehicle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub heading: f32,
+    pub handling: Handling,
+}
+
+impl Vehicle {
+    pub fn new(preset: CarPreset, position: Vec2, heading: f32) -> Self {
+        Self {
+            position,
+            heading,
+            handling: preset.handling(),
+            ..Default::default()
+        }
+    }
+
+    pub fn forward(&self) -> Vec2 {
+        Vec2::new(self.heading.cos(), self.heading.sin())
+    }
+
+    /// Signed speed along the direction the car is facing. Negative while reversing.
+    pub fn speed(&self) -> f32 {
+        self.velocity.dot(self.forward())
+    }
+
+    pub fn update(&mut self, controls: &Controls, delta: f32) {
+        let handling = self.handling;
+        let forward = self.forward();
+        let lateral = forward.perp();
+
+        let mut forward_speed = self.velocity.dot(forward);
+        let mut lateral_speed = self.velocity.dot(lateral);
+
+        // Down brakes while rolling forwards and reverses once the car has stopped.
+        let braking = if forward_speed > 0.0 {
+            handling.braking
+        } else {
+            handling.reverse_acceleration
+        };
+        forward_speed +=
+            (controls.throttle * handling.acceleration - controls.brake * braking) * delta;
+
+        // Resistance slows the car down, but never pushes it backwards.
+        let resistance =
+            (handling.rolling_resistance + handling.drag * forward_speed * forward_speed) * delta;
+        forward_speed -= forward_speed.signum() * resistance.min(forward_speed.abs());
+        forward_speed = forward_speed.clamp(-handling.reverse_speed, handling.top_speed);
+
+        lateral_speed *= (-handling.grip * delta).exp();
+
+        let steering_scale = (forward_speed.abs() / handling.steering_full_speed).min(1.0);
+        self.heading += controls.steering
+            * handling.steering_rate
+            * steering_scale
+            * forward_speed.signum()
+            * delta;
+
+        self.velocity = forward * forward_speed + lateral * lateral_speed;
+        self.position += self.velocity * delta;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA: f32 = 1.0 / 60.0;
+
+    fn drive(car: &mut Vehicle, controls: Controls, seconds: f32) {
+        for _ in 0..(seconds / DELTA) as usize {
+            car.update(&controls, DELTA);
+        }
+    }
+
+    #[test]
+    fn speed_is_held_at_the_top_speed() {
+        for preset in [CarPreset::Green, CarPreset::Yellow] {
+            let mut car = Vehicle::new(preset, Vec2::ZERO, 0.0);
+            let throttle = Controls {
+                throttle: 1.0,
+                ..Default::default()
+            };
+            drive(&mut car, throttle, 20.0);
+
+            assert!(car.speed() <= preset.handling().top_speed);
+            assert!(car.speed() > preset.handling().top_speed * 0.9);
+        }
+    }
+
+    #[test]
+    fn car_coasts_to_a_stop_without_throttle() {
+        let mut car = Vehicle::new(CarPreset::Green, Vec2::ZERO, 0.0);
+        car.velocity = Vec2::new(300.0, 0.0);
+        drive(&mut car, Controls::default(), 10.0);
+
+        assert_eq!(car.velocity, Vec2::ZERO);
+        assert!(car.position.x > 0.0);
+    }
+
+    #[test]
+    fn holding_the_brake_stops_the_car_then_reverses_it() {
+        let mut car = Vehicle::new(CarPreset::Green, Vec2::ZERO, 0.0);
+        car.velocity = Vec2::new(200.0, 0.0);
+        let brake = Controls {
+            brake: 1.0,
+            ..Default::default()
+        };
+
+        drive(&mut car, brake, 0.2);
+        assert!(car.speed() < 200.0);
+        drive(&mut car, brake, 5.0);
+
+        assert!(car.speed() < 0.0);
+        assert!(car.speed() >= -car.handling.reverse_speed);
+    }
+}